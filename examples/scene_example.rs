@@ -5,8 +5,8 @@ pub mod util;
 
 use std::{sync::Arc, thread::sleep, time::Duration};
 
-use chaos_vk::{graphics::{buffer::{VkBuffer, VkIterBuffer}, command::{CommandBufferType, VkBuilder}, mesh::mesh::Mesh, presenter::Presenter, utils::{descriptor_set, instancing_pipeline, render_pass_with_depth}, vertex::PosInstanceData, vk::Vk}, imgui_renderer::ImGui};
-use glam::Mat4;
+use chaos_vk::{graphics::{buffer::{VkBuffer, VkIterBuffer}, command::{CommandBufferType, VkBuilder}, culling::visible_meshes, debug::DebugDraw, hot_reload::ShaderKind, mesh::mesh::Mesh, post::PostProcess, presenter::Presenter, shadow::CascadedShadows, stats::FrameStats, utils::{descriptor_set, instancing_pipeline, render_pass_with_depth}, vertex::PosInstanceData, vk::Vk}, imgui_renderer::ImGui};
+use glam::{Mat4, Vec3, Vec4};
 use scene_loader::{geometry::sphere, loader::Scene, renderer::Renderer, shaders::{self, vs}};
 use util::math::rand_betw;
 use vulkano::{command_buffer::{RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::WriteDescriptorSet, pipeline::{graphics::viewport::Viewport, GraphicsPipeline, Pipeline}};
@@ -49,19 +49,33 @@ fn example() {
     let vs = shaders::vs::load(vk.device.clone()).unwrap();
     let fs = shaders::fs::load(vk.device.clone()).unwrap();
 
+    /* the presenter still wants a render pass for its framebuffers, the scene itself goes through post */
     let rp = render_pass_with_depth(vk.clone(), Some(presenter.swapchain.clone()));
+    let mut post = PostProcess::new(vk.clone(), presenter.swapchain.image_format());
+    let mut shadows = CascadedShadows::new(vk.clone(), 2048, 4);
+    let mut debug = DebugDraw::new(vk.clone(), post.scene_pass.clone());
 
-    let pipeline = instancing_pipeline(vk.clone(), vs.clone(), fs.clone(), rp.clone(), Viewport {
+    let pipeline = instancing_pipeline(vk.clone(), vs.clone(), fs.clone(), post.scene_pass.clone(), Viewport {
         offset: [0.0, 0.0],
         extent: size.into(),
         depth_range: 0.0..=1.0,
     });
+    /* lets `Mesh::build_commands` find the `Model` block of the example shaders */
+    vk.reflections.insert_glsl(pipeline.layout(), &[
+        (shaders::vs::SOURCE, ShaderKind::Vertex),
+        (shaders::fs::SOURCE, ShaderKind::Fragment),
+    ]).unwrap();
 
     presenter.window_resized = true;
     presenter.recreate(vk.clone(), rp.clone(), window.clone());
+    post.resize(vk.clone(), &presenter.images);
 
-    let sphere = sphere(5, 1.0);
-    renderer.meshes.push(Mesh::new(vk.clone(), &sphere.vertices, &sphere.indices));
+    let sphere_mesh = sphere(32, 1.0);
+    renderer.meshes.push(Mesh::with_uvs(vk.clone(), &sphere_mesh.vertices, &sphere_mesh.indices, &sphere_mesh.uvs));
+    for (iterations, screen_size) in [(16, 0.15), (8, 0.06), (4, 0.02)] {
+        let lod = sphere(iterations, 1.0);
+        renderer.meshes[0].add_lod(vk.clone(), &lod.vertices, &lod.indices, screen_size);
+    }
 
     let mut cursor_x = 0.0;
     let mut cursor_y = 0.0;
@@ -73,8 +87,11 @@ fn example() {
     renderer.meshes[0].instances = data.clone();
     let instance_buffer = VkIterBuffer::vertex(vk.allocators.clone(), data);
     renderer.meshes[0].ibo = instance_buffer;
+    renderer.meshes[0].update_bounds();
 
     let mut dt = 0.0;
+    let mut stats = FrameStats::new();
+    let mut show_lods = false;
 
     el.run(move |event, _target, control_flow| {
         control_flow.set_poll();
@@ -138,15 +155,40 @@ fn example() {
                 let frame = imgui.frame(&window);
                 frame.text("hello, world!");
                 frame.text(format!("dt:{:.1}", dt*1000.0));
+                post.ui(frame);
+                stats.ui(frame);
+                frame.checkbox("lod overlay", &mut show_lods);
+
+                debug.clear();
+                debug.grid(Vec3::new(0.0, -12.0, 0.0), 100.0, 20, Vec4::new(0.5, 0.5, 0.5, 0.5));
+                debug.axes(Mat4::IDENTITY, 5.0);
+                debug.text_3d(Vec3::ZERO, Vec4::ONE, "origin");
                 
                 presenter.recreate(vk.clone(), rp.clone(), window.clone());
+                let extent = presenter.images[0].extent();
+                if post.extent() != Some([extent[0], extent[1]]) {
+                    post.resize(vk.clone(), &presenter.images);
+                }
 
                 renderer.update(dt);
+                for mesh in &mut renderer.meshes {
+                    mesh.select_instance_lods(vk.clone(), &renderer.camera);
+                    if show_lods {
+                        debug.mesh_lods(mesh);
+                    }
+                }
+                debug.draw_text(frame, &renderer.camera);
+                shadows.update(&renderer.camera);
+                stats.reset();
                 presenter.cmd_bufs = get_cmd_bufs(
                     vk.clone(), 
                     &renderer,
                     &mut imgui,
                     &presenter, 
+                    &post,
+                    &shadows,
+                    &debug,
+                    &mut stats,
                     pipeline.clone()
                 );
                 
@@ -156,21 +198,35 @@ fn example() {
                 dt = now.elapsed().as_secs_f32();
             }
 
-            Event::LoopDestroyed => {println!("EXIT");}
+            Event::LoopDestroyed => {
+                vk.pipeline_cache.save().expect("failed to save the pipeline cache");
+                println!("EXIT");
+            }
             _ => {}
         }
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn get_cmd_bufs(
     vk: Arc<Vk>, 
     renderer: &Renderer,
     imgui_renderer: &mut ImGui,
     presenter: &Presenter,
+    post: &PostProcess,
+    shadows: &CascadedShadows,
+    debug: &DebugDraw,
+    stats: &mut FrameStats,
     pipeline: Arc<GraphicsPipeline>,
 ) -> Vec<CommandBufferType> {
     let mut cmd_bufs: Vec<CommandBufferType> = vec![];
 
+    /* shadows still draw every mesh, casters can be outside of the view */
+    let visible = visible_meshes(&renderer.meshes, &renderer.camera.frustum(), stats);
+    for mesh in &visible {
+        stats.mesh(mesh);
+    }
+
     let ubo = VkBuffer::uniform(vk.allocators.clone(), vs::Camera {
         view: renderer.camera.get_view(),
         proj: renderer.camera.get_proj(),
@@ -183,20 +239,32 @@ pub fn get_cmd_bufs(
         [WriteDescriptorSet::buffer(0, ubo.content.clone())]
     ).0;
 
+    let shadow_ubo = VkBuffer::uniform(vk.allocators.clone(), shadows.data());
+    let shadow_desc_set = descriptor_set(
+        vk.clone(),
+        2,
+        pipeline.clone(),
+        [
+            WriteDescriptorSet::buffer(0, shadow_ubo.content.clone()),
+            shadows.map.write(vk.clone(), 1),
+        ]
+    ).0;
+
     let render_passes = imgui_renderer.get_renderpasses(
         presenter.images.clone(),
         vk.clone()
     );
 
-    let mut i = 0;
-    for framebuffer in &presenter.framebuffers.clone() {
+    for (i, render_pass) in render_passes.iter().enumerate() {
         let mut builder = VkBuilder::new_multiple(vk.clone());
 
+        shadows.build_commands(vk.clone(), &mut builder.0, &renderer.meshes).unwrap();
+
         builder.0
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    clear_values: vec![Some([0.1, 0.2, 0.3, 1.0].into()), Some(1.0.into())],
-                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+                    clear_values: post.clear_values(),
+                    ..RenderPassBeginInfo::framebuffer(post.scene_framebuffer())
                 },
                 SubpassBeginInfo {
                     contents: SubpassContents::Inline,
@@ -212,15 +280,30 @@ pub fn get_cmd_bufs(
                 0, 
                 camera_desc_set.clone(),
             )
+            .unwrap()
+            .bind_descriptor_sets(
+                vulkano::pipeline::PipelineBindPoint::Graphics, 
+                pipeline.layout().clone(), 
+                2, 
+                shadow_desc_set.clone(),
+            )
             .unwrap();
 
-        for mesh in &renderer.meshes {
-            mesh.build_commands(vk.clone(), &mut builder.0, pipeline.clone());
+        for mesh in &visible {
+            mesh.build_commands(vk.clone(), &mut builder.0, pipeline.clone()).unwrap();
         }
 
+        let extent = post.extent().unwrap();
+        debug.build_commands(vk.clone(), &mut builder.0, &renderer.camera, Viewport {
+            offset: [0.0, 0.0],
+            extent: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..=1.0,
+        });
+
         builder.0.end_render_pass(Default::default()).unwrap();
 
-        let render_pass = &render_passes[i];
+        post.build_commands(vk.clone(), &mut builder.0, i);
+
         builder.0.begin_render_pass(
             RenderPassBeginInfo {
                 clear_values: vec![None],
@@ -231,7 +314,7 @@ pub fn get_cmd_bufs(
                 contents: SubpassContents::SecondaryCommandBuffers,
                 ..Default::default()
             },
-        ).expect(&format!("failed to start imgui render pass on framebuffer {:?}", framebuffer));
+        ).expect(&format!("failed to start imgui render pass on framebuffer {:?}", render_pass.framebuffer));
 
         builder.0.execute_commands(render_pass.cmd_buf.clone()).unwrap();
         
@@ -241,8 +324,6 @@ pub fn get_cmd_bufs(
         cmd_bufs.push(
             builder.command_buffer()
         );
-
-        i += 1;
     }
 
     cmd_bufs
@@ -5,11 +5,13 @@ use chaos_vk::graphics::vertex::PosVertex;
 pub struct GeometryData {
     pub vertices: Vec<PosVertex>,
     pub indices: Vec<u32>,
+    pub uvs: Vec<[f32; 2]>,
 }
 
 
 pub fn sphere(iterations: usize, radius: f32) -> GeometryData {
     let mut vertices = vec![];
+    let mut uvs = vec![];
     let pi = std::f32::consts::PI;
 
     for lat in 0..=iterations {
@@ -26,14 +28,15 @@ pub fn sphere(iterations: usize, radius: f32) -> GeometryData {
             let y = cos_theta * radius;
             let z = sin_phi * sin_theta * radius;
 
-            // let s = lon as f32 / iterations as f32;
-            // let t = 1.0 - (lat as f32 / iterations as f32);
+            let s = lon as f32 / iterations as f32;
+            let t = 1.0 - (lat as f32 / iterations as f32);
 
             // let normal = vec3(x, y, z).normalize();
 
             vertices.push(PosVertex {
                 pos: [x, y, z],
             });
+            uvs.push([s, t]);
         }
     }
 
@@ -56,5 +59,6 @@ pub fn sphere(iterations: usize, radius: f32) -> GeometryData {
     GeometryData {
         vertices,
         indices,
+        uvs,
     }
 }
\ No newline at end of file
@@ -32,19 +32,17 @@ impl MeshData {
         let scale = vec3(self.sca[0], self.sca[1], self.sca[2]);
         let color = vec3(self.col[0], self.col[1], self.col[2]);
 
-        Mesh {
-            vertices: vertices.clone(),
-            indices: self.inds.clone(),
-            instances: instances.clone(),
-            position,
-            rotation,
-            scale,
-            color,
-            
-            ibo: VkIterBuffer::vertex(vk.allocators.clone(), instances),
-            vbo: VkIterBuffer::vertex(vk.allocators.clone(), vertices),
-            ebo: VkIterBuffer::index(vk.allocators.clone(), self.inds.clone()),
-        }
+        /* normals are not stored, Mesh::new computes them */
+        let mut mesh = Mesh::new(vk.clone(), &vertices, &self.inds);
+        mesh.instances = instances.clone();
+        mesh.ibo = VkIterBuffer::vertex(vk.allocators.clone(), instances);
+        mesh.update_bounds();
+        mesh.position = position;
+        mesh.rotation = rotation;
+        mesh.scale = scale;
+        mesh.color = color;
+
+        mesh
     }
 
     pub fn from_mesh(mesh: &Mesh) -> Self {
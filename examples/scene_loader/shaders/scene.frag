@@ -0,0 +1,30 @@
+#version 460
+
+#include "shadow.glsl"
+
+layout(set = 2, binding = 0) uniform Shadow {
+    DirectionalShadowData shadow;
+};
+layout(set = 2, binding = 1) uniform sampler2DArrayShadow shadow_map;
+
+layout(location = 0) out vec4 f_color;
+
+layout(location = 0) in vec4 i_pos;
+layout(location = 1) in vec3 i_world_pos;
+layout(location = 2) in float i_view_depth;
+layout(location = 3) in vec3 i_camera_pos;
+
+void main() {
+    // face normal, turned towards the camera
+    vec3 normal = normalize(cross(dFdx(i_world_pos), dFdy(i_world_pos)));
+    if (dot(normal, i_camera_pos - i_world_pos) < 0.0) {
+        normal = -normal;
+    }
+
+    vec3 to_light = -normalize(shadow.direction.xyz);
+    float diffuse = max(dot(normal, to_light), 0.0);
+    float lit = directional_shadow(shadow_map, shadow, i_world_pos, normal, i_view_depth);
+
+    vec3 albedo = clamp(abs(i_pos.xyz) / 10.0, 0.05, 1.0);
+    f_color = vec4(albedo * (0.15 + diffuse * lit), 1.0);
+}
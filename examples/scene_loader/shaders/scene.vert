@@ -0,0 +1,30 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+
+layout (location = 1) in vec3 ofs; // per instance
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+layout(set = 1, binding = 0) uniform Model {
+    mat4 model;
+};
+
+layout(location = 0) out vec4 o_pos;
+layout(location = 1) out vec3 o_world_pos;
+layout(location = 2) out float o_view_depth;
+layout(location = 3) out vec3 o_camera_pos;
+
+void main() {
+    vec4 world = model * vec4(pos + ofs, 1.0);
+    vec4 view_pos = view * world;
+    gl_Position = proj * view_pos;
+
+    o_pos = vec4(pos + ofs, 1.0);
+    o_world_pos = world.xyz;
+    o_view_depth = abs(view_pos.z);
+    o_camera_pos = inverse(view)[3].xyz;
+}
@@ -0,0 +1,444 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Quat, Vec3};
+use vulkano::{descriptor_set::WriteDescriptorSet, pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint}};
+
+use super::{buffer::VkIterBuffer, command::BuilderType, lighting::{lit_fs, ShadingModel}, mesh::mesh::Mesh, pipeline::GraphicsPipelineBuilder, reflect::BindError, utils::descriptor_set, vertex::{NormalVertex, PosInstanceData, PosVertex, SkinVertex}, vk::Vk};
+
+pub mod shaders;
+
+/// Set of the joint matrices in the skinned shaders, after the lights
+pub const JOINT_SET: u32 = 3;
+
+/// Translation, rotation and scale of a joint relative to its parent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+
+        Self { translation, rotation, scale }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Linear translation and scale, slerped rotation
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub name: String,
+    /// Always before this joint in `Skeleton::joints`
+    pub parent: Option<usize>,
+    /// From model space to the space of the joint in the bind pose
+    pub inverse_bind: Mat4,
+    /// Local transform when no clip animates the joint
+    pub rest: Transform,
+}
+
+/// Joint hierarchy, parents are stored before their children
+#[derive(Clone, Debug, Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        for (i, joint) in joints.iter().enumerate() {
+            assert!(joint.parent.is_none_or(|parent| parent < i), "joint {} comes before its parent", joint.name);
+        }
+
+        Self { joints }
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Model space transform of every joint for the local transforms `pose`
+    pub fn global_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+        let mut globals: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+
+        for (joint, local) in self.joints.iter().zip(pose) {
+            let global = match joint.parent {
+                Some(parent) => globals[parent] * local.matrix(),
+                None => local.matrix(),
+            };
+            globals.push(global);
+        }
+
+        globals
+    }
+
+    /// `global_matrices` times the inverse bind matrices, what the vertex shader skins with
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+        self.global_matrices(pose)
+            .into_iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global * joint.inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Holds every key until the next one
+    Step,
+    /// Lerp for translations and scales, slerp for rotations
+    #[default]
+    Linear,
+}
+
+/// One value per key of `Channel::times`
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+/// Keyframes of one property of one joint
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    /// Increasing, in seconds
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+    pub interpolation: Interpolation,
+}
+
+impl Channel {
+    /// Writes the value at `time` into `transform`, clamped to the first and last keys
+    pub fn sample(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() {
+            return;
+        }
+
+        let next = self.times.partition_point(|&key| key <= time).min(self.times.len() - 1);
+        let prev = next.saturating_sub(1);
+
+        let span = self.times[next] - self.times[prev];
+        let t = match self.interpolation {
+            Interpolation::Linear if span > 0.0 => ((time - self.times[prev]) / span).clamp(0.0, 1.0),
+            _ if time >= self.times[next] => 1.0,
+            _ => 0.0,
+        };
+
+        match &self.values {
+            ChannelValues::Translation(values) => transform.translation = values[prev].lerp(values[next], t),
+            ChannelValues::Rotation(values) => transform.rotation = values[prev].slerp(values[next], t),
+            ChannelValues::Scale(values) => transform.scale = values[prev].lerp(values[next], t),
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    /// Last key of every channel
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    pub fn new(name: impl Into<String>, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .map(Channel::duration)
+            .fold(0.0, f32::max);
+
+        Self {
+            name: name.into(),
+            channels,
+            duration,
+        }
+    }
+
+    /// Overrides the animated properties of `pose`, the others keep their value
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.sample(time, transform);
+            }
+        }
+    }
+}
+
+/// A clip being played by an `Animator`
+#[derive(Clone, Copy, Debug)]
+pub struct ClipState {
+    pub clip: usize,
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub weight: f32,
+    /// `weight` moves towards it by `fade_speed` per second, the state is removed once both reach 0
+    pub target_weight: f32,
+    pub fade_speed: f32,
+}
+
+/// Plays and blends `clips` on `skeleton`. Call `update` every frame, then `bind` before drawing `SkinnedMesh`es.
+///
+/// ```ignore
+/// let mut animator = Animator::new(skeleton, vec![idle, walk]);
+/// animator.play(0);
+/// // later
+/// animator.crossfade(1, 0.3);
+///
+/// animator.update(dt);
+/// animator.bind(vk.clone(), &mut builder, pipeline.clone());
+/// character.build_commands(vk.clone(), &mut builder, pipeline.clone())?;
+/// ```
+pub struct Animator {
+    pub skeleton: Skeleton,
+    pub clips: Vec<AnimationClip>,
+    pub states: Vec<ClipState>,
+
+    pose: Vec<Transform>,
+    joint_matrices: Vec<Mat4>,
+}
+
+impl Animator {
+    pub fn new(skeleton: Skeleton, clips: Vec<AnimationClip>) -> Self {
+        let pose = skeleton.rest_pose();
+        let joint_matrices = skeleton.joint_matrices(&pose);
+
+        Self {
+            skeleton,
+            clips,
+            states: vec![],
+
+            pose,
+            joint_matrices,
+        }
+    }
+
+    /// Index of the clip called `name`
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+
+    /// Stops everything else and loops `clip`
+    pub fn play(&mut self, clip: usize) {
+        self.states.clear();
+        self.blend(clip, 1.0);
+    }
+
+    /// Adds `clip` on top of the playing ones with a fixed `weight`
+    pub fn blend(&mut self, clip: usize, weight: f32) {
+        self.states.push(ClipState {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            weight,
+            target_weight: weight,
+            fade_speed: 0.0,
+        });
+    }
+
+    /// Fades the playing clips out and `clip` in over `duration` seconds
+    pub fn crossfade(&mut self, clip: usize, duration: f32) {
+        let fade_speed = 1.0 / duration.max(f32::EPSILON);
+
+        for state in &mut self.states {
+            state.target_weight = 0.0;
+            state.fade_speed = fade_speed;
+        }
+
+        self.blend(clip, 0.0);
+        let state = self.states.last_mut().unwrap();
+        state.target_weight = 1.0;
+        state.fade_speed = fade_speed;
+    }
+
+    /// Local transforms of the last `update`
+    pub fn pose(&self) -> &[Transform] {
+        &self.pose
+    }
+
+    /// Skinning matrices of the last `update`
+    pub fn joint_matrices(&self) -> &[Mat4] {
+        &self.joint_matrices
+    }
+
+    /// Advances the clips by `dt` seconds and blends them by weight into the pose
+    pub fn update(&mut self, dt: f32) {
+        for state in &mut self.states {
+            let duration = self.clips[state.clip].duration;
+
+            state.time += dt * state.speed;
+            if state.looping && duration > 0.0 {
+                state.time = state.time.rem_euclid(duration);
+            } else {
+                state.time = state.time.clamp(0.0, duration);
+            }
+
+            let step = state.fade_speed * dt;
+            state.weight += (state.target_weight - state.weight).clamp(-step, step);
+        }
+        self.states.retain(|state| state.weight > 0.0 || state.target_weight > 0.0);
+
+        self.pose = self.blended_pose();
+        self.joint_matrices = self.skeleton.joint_matrices(&self.pose);
+    }
+
+    /// Joint matrices at binding 0, never empty
+    pub fn write(&self, vk: Arc<Vk>) -> WriteDescriptorSet {
+        let mut matrices = self.joint_matrices
+            .iter()
+            .map(Mat4::to_cols_array_2d)
+            .collect::<Vec<_>>();
+        if matrices.is_empty() {
+            matrices.push(Mat4::IDENTITY.to_cols_array_2d());
+        }
+
+        let joints = VkIterBuffer::storage(vk.allocators.clone(), matrices.into_iter());
+
+        WriteDescriptorSet::buffer(0, joints.content)
+    }
+
+    /// Warning: this function assumes `pipeline` has already been bound
+    pub fn bind(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) {
+        let set = descriptor_set(vk.clone(), JOINT_SET as usize, pipeline.clone(), [self.write(vk)]).0;
+
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), JOINT_SET, set)
+            .unwrap();
+    }
+
+    /// Weighted average of the sampled clips, the rest pose without any weight
+    fn blended_pose(&self) -> Vec<Transform> {
+        let rest = self.skeleton.rest_pose();
+        let total = self.states.iter().map(|state| state.weight).sum::<f32>();
+        if total <= 0.0 {
+            return rest;
+        }
+
+        let mut translations = vec![Vec3::ZERO; rest.len()];
+        let mut scales = vec![Vec3::ZERO; rest.len()];
+        let mut rotations = vec![Quat::from_xyzw(0.0, 0.0, 0.0, 0.0); rest.len()];
+
+        for state in &self.states {
+            let weight = state.weight / total;
+            let mut pose = rest.clone();
+            self.clips[state.clip].sample(state.time, &mut pose);
+
+            for (i, transform) in pose.iter().enumerate() {
+                translations[i] += transform.translation * weight;
+                scales[i] += transform.scale * weight;
+
+                /* q and -q are the same rotation, keep them in one hemisphere before summing */
+                let rotation = if rotations[i].dot(transform.rotation) < 0.0 { -transform.rotation } else { transform.rotation };
+                rotations[i] = rotations[i] + rotation * weight;
+            }
+        }
+
+        (0..rest.len())
+            .map(|i| Transform {
+                translation: translations[i],
+                rotation: rotations[i].normalize(),
+                scale: scales[i],
+            })
+            .collect()
+    }
+}
+
+/// `Mesh` with joints and weights per vertex, drawn with `skinned_pipeline`
+#[derive(Clone)]
+pub struct SkinnedMesh {
+    pub mesh: Mesh,
+    pub skin: Vec<SkinVertex>,
+    pub sbo: VkIterBuffer<SkinVertex>,
+}
+
+impl SkinnedMesh {
+    /// `skin` has one entry per vertex. The bounds of `mesh` are the ones of the bind pose.
+    pub fn new(vk: Arc<Vk>, vertices: &Vec<PosVertex>, indices: &Vec<u32>, skin: Vec<SkinVertex>) -> Self {
+        assert_eq!(vertices.len(), skin.len(), "one skin vertex per vertex");
+
+        Self {
+            mesh: Mesh::new(vk.clone(), vertices, indices),
+            sbo: VkIterBuffer::vertex(vk.allocators.clone(), skin.clone()),
+            skin,
+        }
+    }
+
+    /// Uploads `skin` again, see `Mesh::rebuild` for the rest
+    pub fn rebuild(&mut self, vk: Arc<Vk>) {
+        self.mesh.rebuild(vk.clone());
+        self.sbo = VkIterBuffer::vertex(vk.allocators.clone(), self.skin.clone());
+    }
+
+    /// Warning: this function assumes `pipeline` has already been bound, with the joints of `Animator::bind`.
+    /// Vertex buffers are the ones of `Mesh::build_commands` and `SkinVertex` at binding 3.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) -> Result<(), BindError> {
+        let mesh = &self.mesh;
+        let instances = mesh.instance_buffer(&pipeline)?;
+        mesh.bind_model(vk, builder, pipeline)?;
+
+        builder
+            .bind_vertex_buffers(0, (
+                mesh.vbo.content.clone(),
+                instances,
+                mesh.nbo.content.clone(),
+                self.sbo.content.clone(),
+            ))
+            .unwrap()
+            .bind_index_buffer(mesh.ebo.content.clone())
+            .unwrap()
+            .draw_indexed(mesh.ebo.content.len() as u32, mesh.instance_count(), 0, 0, 0)
+            .unwrap();
+
+        Ok(())
+    }
+}
+
+/// `lighting::lit_pipeline` skinned by the joint matrices, ready to be drawn with `SkinnedMesh::build_commands`.
+///
+/// Sets 0 to 2 are the ones of `lit_pipeline`, set 3 the joints (see `Animator::bind`).
+pub fn skinned_pipeline(vk: Arc<Vk>, model: ShadingModel) -> GraphicsPipelineBuilder {
+    let vs = shaders::skinned_vs::load(vk.device.clone()).unwrap();
+    let (fs, fs_source) = lit_fs(vk, model);
+
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .instance::<PosInstanceData>()
+        .vertex::<NormalVertex>()
+        .vertex::<SkinVertex>()
+        .reflect(shaders::skinned_vs::SOURCE, fs_source)
+}
@@ -0,0 +1,10 @@
+/// `lighting::shaders::vs` skinned by the joint matrices of `Animator::bind`
+pub mod skinned_vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/skinned.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/skinned.vert");
+}
@@ -0,0 +1,211 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use vulkano::{descriptor_set::{layout::{DescriptorBindingFlags, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType}, PersistentDescriptorSet, WriteDescriptorSet}, image::{sampler::Sampler, view::ImageView}, pipeline::{layout::PipelineDescriptorSetLayoutCreateInfo, Pipeline}, shader::ShaderStages};
+
+use super::{command::BuilderType, utils::descriptor_set, vk::Vk};
+
+/// Per stage samplers left to the other bindings of the pipelines using the table
+pub const RESERVED_SAMPLERS: u32 = 16;
+
+/// Stable index of a texture inside a `BindlessTextures` table.
+/// Pass it to the shader through a push constant or an instance attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureHandle(pub u32);
+
+/// One big array of sampled images at binding 0 of a single descriptor set.
+///
+/// With `vk.bindless` the shader declares
+/// ```glsl
+/// #extension GL_EXT_nonuniform_qualifier : require
+/// layout(set = N, binding = 0) uniform sampler2D textures[];
+/// // texture(textures[nonuniformEXT(index)], uv)
+/// ```
+/// and the pipeline layout must be patched with `patch_layout`.
+/// Without it, `bind` falls back to a per-draw set holding only the drawn texture,
+/// for a shader declaring `layout(set = N, binding = 0) uniform sampler2D tex;`
+pub struct BindlessTextures {
+    pub capacity: u32,
+    bindless: bool,
+    layout: Option<Arc<DescriptorSetLayout>>,
+    textures: Vec<Option<(Arc<ImageView>, Arc<Sampler>)>>,
+    free: Vec<u32>,
+    set: Option<Arc<PersistentDescriptorSet>>,
+}
+
+impl BindlessTextures {
+    /// `capacity` is clamped to the per stage sampled image and sampler limits,
+    /// minus `RESERVED_SAMPLERS` for the other textures of the stage (material, shadow maps, ...)
+    pub fn new(vk: Arc<Vk>, capacity: u32) -> Self {
+        let properties = vk.physical_device.properties();
+        let capacity = capacity
+            .min(properties.max_per_stage_descriptor_sampled_images.saturating_sub(RESERVED_SAMPLERS))
+            .min(properties.max_per_stage_descriptor_samplers.saturating_sub(RESERVED_SAMPLERS))
+            .max(1);
+
+        let layout = vk.bindless.then(|| {
+            DescriptorSetLayout::new(vk.device.clone(), layout_create_info(capacity)).unwrap()
+        });
+
+        Self {
+            capacity,
+            bindless: vk.bindless,
+            layout,
+            textures: Vec::new(),
+            free: Vec::new(),
+            set: None,
+        }
+    }
+
+    pub fn is_bindless(&self) -> bool {
+        self.bindless
+    }
+
+    /// Registers a texture, reusing the slot of a removed one if there is any
+    pub fn add(&mut self, view: Arc<ImageView>, sampler: Arc<Sampler>) -> TextureHandle {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                assert!((self.textures.len() as u32) < self.capacity, "bindless texture table is full");
+                self.textures.push(None);
+                self.textures.len() as u32 - 1
+            }
+        };
+
+        self.textures[index as usize] = Some((view, sampler));
+        self.set = None;
+
+        TextureHandle(index)
+    }
+
+    /// Swaps the texture behind `handle`, shaders keep using the same index
+    pub fn replace(&mut self, handle: TextureHandle, view: Arc<ImageView>, sampler: Arc<Sampler>) {
+        self.textures[handle.0 as usize] = Some((view, sampler));
+        self.set = None;
+    }
+
+    /// The slot is left unbound until reused, the handle must not be drawn with afterwards
+    pub fn remove(&mut self, handle: TextureHandle) {
+        if self.textures[handle.0 as usize].take().is_some() {
+            self.free.push(handle.0);
+            self.set = None;
+        }
+    }
+
+    pub fn get(&self, handle: TextureHandle) -> Option<&(Arc<ImageView>, Arc<Sampler>)> {
+        self.textures.get(handle.0 as usize)?.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces `set` of a layout built by `PipelineDescriptorSetLayoutCreateInfo::from_stages`
+    /// with the variable count array, so it is compatible with the table's descriptor set.
+    /// Does nothing in fallback mode.
+    pub fn patch_layout(&self, info: &mut PipelineDescriptorSetLayoutCreateInfo, set: usize) {
+        if self.bindless {
+            info.set_layouts[set] = layout_create_info(self.capacity);
+        }
+    }
+
+    /// The table's descriptor set, rebuilt after textures were added, replaced or removed.
+    /// Sets handed out earlier stay valid for the command buffers using them.
+    ///
+    /// Returns `None` in fallback mode
+    pub fn set(&mut self, vk: Arc<Vk>) -> Option<Arc<PersistentDescriptorSet>> {
+        let layout = self.layout.clone()?;
+
+        let set = self.set.get_or_insert_with(|| {
+            let writes = self.textures
+                .iter()
+                .enumerate()
+                .filter_map(|(i, texture)| {
+                    let (view, sampler) = texture.clone()?;
+                    Some(WriteDescriptorSet::image_view_sampler_array(0, i as u32, [(view, sampler)]))
+                })
+                .collect::<Vec<_>>();
+
+            PersistentDescriptorSet::new_variable(
+                &vk.allocators.descriptor_set,
+                layout,
+                (self.textures.len() as u32).max(1),
+                writes,
+                [],
+            )
+            .unwrap()
+        });
+
+        Some(set.clone())
+    }
+
+    /// Warning: this function assumes `pipeline` has already been bounded
+    ///
+    /// Bindless: binds the whole table at `set_index`, `handle` is only used by the shader.
+    /// Fallback: binds a set holding just the texture behind `handle`.
+    pub fn bind<P: Pipeline>(
+        &mut self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        pipeline: Arc<P>,
+        set_index: u32,
+        handle: TextureHandle,
+    ) {
+        let set = match self.set(vk.clone()) {
+            Some(set) => set,
+            None => {
+                let (view, sampler) = self.get(handle).expect("texture handle was removed").clone();
+                descriptor_set(
+                    vk,
+                    set_index as usize,
+                    pipeline.clone(),
+                    [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+                ).0
+            }
+        };
+
+        builder
+            .bind_descriptor_sets(pipeline.bind_point(), pipeline.layout().clone(), set_index, set)
+            .unwrap();
+    }
+
+    /// Binds the table once for a whole batch of draws indexing it.
+    /// Returns false in fallback mode, where `bind` has to be called per draw instead.
+    pub fn bind_table<P: Pipeline>(
+        &mut self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        pipeline: Arc<P>,
+        set_index: u32,
+    ) -> bool {
+        let Some(set) = self.set(vk) else {
+            return false;
+        };
+
+        builder
+            .bind_descriptor_sets(pipeline.bind_point(), pipeline.layout().clone(), set_index, set)
+            .unwrap();
+
+        true
+    }
+}
+
+fn layout_create_info(capacity: u32) -> DescriptorSetLayoutCreateInfo {
+    DescriptorSetLayoutCreateInfo {
+        bindings: BTreeMap::from([(
+            0,
+            DescriptorSetLayoutBinding {
+                binding_flags: DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+                    | DescriptorBindingFlags::PARTIALLY_BOUND,
+                descriptor_count: capacity,
+                stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                ..DescriptorSetLayoutBinding::descriptor_type(DescriptorType::CombinedImageSampler)
+            },
+        )]),
+        ..Default::default()
+    }
+}
+
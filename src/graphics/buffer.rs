@@ -323,4 +323,53 @@ impl<T: BufferContents> VkIterBuffer<T> {
             content: buffer,
         }
     }
+
+    /// Indirect draw commands written by compute shaders and updated from command buffers
+    pub fn indirect_storage<I>(allocators: Arc<MemAllocators>, iter_data: I) -> Self 
+    where 
+        T: BufferContents,
+        I: Iterator<Item = T> + ExactSizeIterator
+    {
+        let buffer = Buffer::from_iter(
+            allocators.memory.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::INDIRECT_BUFFER | BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            iter_data,
+        )
+        .expect("failed to create buffer");
+
+        Self {
+            content: buffer,
+        }
+    }
+
+    /// Uninitialized vertex buffer of `len` elements only written by the GPU, e.g. compacted instances
+    pub fn vertex_storage(allocators: Arc<MemAllocators>, len: u64) -> Self 
+    where 
+        T: BufferContents 
+    {
+        let buffer = Buffer::new_slice(
+            allocators.memory.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER | BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            len,
+        )
+        .expect("failed to create buffer");
+
+        Self {
+            content: buffer,
+        }
+    }
 }
\ No newline at end of file
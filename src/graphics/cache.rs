@@ -0,0 +1,320 @@
+use std::{collections::HashMap, fs, hash::{Hash, Hasher}, io, ops::RangeInclusive, path::{Path, PathBuf}, sync::{Arc, Mutex, Weak}};
+
+use vulkano::{device::Device, format::Format, image::{sampler::{BorderColor, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE}, view::{ImageView, ImageViewCreateInfo, ImageViewType}, Image, ImageSubresourceRange, ImageUsage}, pipeline::{cache::{PipelineCache, PipelineCacheCreateInfo}, graphics::depth_stencil::CompareOp}, Handle, VulkanObject};
+
+/// Hashable description of a sampler, used as the key of `SamplerCache`
+#[derive(Clone, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    pub address_mode: [SamplerAddressMode; 3],
+    pub border_color: BorderColor,
+    /// Clamped to the device limit, ignored when `sampler_anisotropy` is not enabled
+    pub anisotropy: Option<f32>,
+    /// Depth comparison, for shadow maps
+    pub compare: Option<CompareOp>,
+    pub lod: RangeInclusive<f32>,
+    pub mip_lod_bias: f32,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+impl SamplerDesc {
+    /// Linear filtering over every mip level, repeating
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::Repeat; 3],
+            border_color: BorderColor::FloatTransparentBlack,
+            anisotropy: None,
+            compare: None,
+            lod: 0.0..=LOD_CLAMP_NONE,
+            mip_lod_bias: 0.0,
+        }
+    }
+
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            ..Self::linear()
+        }
+    }
+
+    /// Linear depth comparison clamped to an opaque white border, so everything outside the map is lit
+    pub fn shadow() -> Self {
+        Self {
+            address_mode: [SamplerAddressMode::ClampToBorder; 3],
+            border_color: BorderColor::FloatOpaqueWhite,
+            compare: Some(CompareOp::LessOrEqual),
+            lod: 0.0..=0.0,
+            ..Self::linear()
+        }
+    }
+
+    pub fn address_mode(mut self, mode: SamplerAddressMode) -> Self {
+        self.address_mode = [mode; 3];
+        self
+    }
+
+    pub fn anisotropy(mut self, max: f32) -> Self {
+        self.anisotropy = Some(max);
+        self
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_mode == other.mipmap_mode
+            && self.address_mode == other.address_mode
+            && self.border_color == other.border_color
+            && self.anisotropy.map(f32::to_bits) == other.anisotropy.map(f32::to_bits)
+            && self.compare == other.compare
+            && self.lod.start().to_bits() == other.lod.start().to_bits()
+            && self.lod.end().to_bits() == other.lod.end().to_bits()
+            && self.mip_lod_bias.to_bits() == other.mip_lod_bias.to_bits()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode.hash(state);
+        self.border_color.hash(state);
+        self.anisotropy.map(f32::to_bits).hash(state);
+        self.compare.hash(state);
+        self.lod.start().to_bits().hash(state);
+        self.lod.end().to_bits().hash(state);
+        self.mip_lod_bias.to_bits().hash(state);
+    }
+}
+
+/// Creates each distinct sampler once and hands out clones of it
+pub struct SamplerCache {
+    device: Arc<Device>,
+    samplers: Mutex<HashMap<SamplerDesc, Arc<Sampler>>>,
+}
+
+impl SamplerCache {
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            device,
+            samplers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, desc: SamplerDesc) -> Arc<Sampler> {
+        let mut samplers = self.samplers.lock().unwrap();
+
+        if let Some(sampler) = samplers.get(&desc) {
+            return sampler.clone();
+        }
+
+        let anisotropy = match desc.anisotropy {
+            Some(max) if self.device.enabled_features().sampler_anisotropy => {
+                let limit = self.device.physical_device().properties().max_sampler_anisotropy;
+                Some(max.clamp(1.0, limit))
+            }
+            _ => None,
+        };
+
+        let sampler = Sampler::new(
+            self.device.clone(),
+            SamplerCreateInfo {
+                mag_filter: desc.mag_filter,
+                min_filter: desc.min_filter,
+                mipmap_mode: desc.mipmap_mode,
+                address_mode: desc.address_mode,
+                border_color: desc.border_color,
+                anisotropy,
+                compare: desc.compare,
+                lod: desc.lod.clone(),
+                mip_lod_bias: desc.mip_lod_bias,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        samplers.insert(desc, sampler.clone());
+        sampler
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ImageViewKey {
+    image: u64,
+    view_type: ImageViewType,
+    format: Format,
+    subresource_range: ImageSubresourceRange,
+    usage: ImageUsage,
+}
+
+/// Creates each distinct view of an image (type, format, subresource range and usage) once.
+///
+/// Only weak references are kept: a view (and its image) is dropped with its last user,
+/// the next `get` creates it again.
+pub struct ImageViewCache {
+    views: Mutex<HashMap<ImageViewKey, Weak<ImageView>>>,
+}
+
+impl ImageViewCache {
+    pub fn new() -> Self {
+        Self {
+            views: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, image: &Arc<Image>, info: ImageViewCreateInfo) -> Arc<ImageView> {
+        let key = ImageViewKey {
+            image: image.handle().as_raw(),
+            view_type: info.view_type,
+            format: info.format,
+            subresource_range: info.subresource_range.clone(),
+            usage: info.usage,
+        };
+
+        let mut views = self.views.lock().unwrap();
+        if let Some(view) = views.get(&key).and_then(Weak::upgrade) {
+            return view;
+        }
+
+        /* a handle can be reused once its image is dropped, forget the dead entries before adding one */
+        views.retain(|_, view| view.strong_count() > 0);
+
+        let view = ImageView::new(image.clone(), info).unwrap();
+        views.insert(key, Arc::downgrade(&view));
+        view
+    }
+
+    /// Same view as `ImageView::new_default`
+    pub fn get_default(&self, image: &Arc<Image>) -> Arc<ImageView> {
+        self.get(image, ImageViewCreateInfo::from_image(image))
+    }
+
+    /// Forgets the views that were dropped, `get` already does it when it creates a view
+    pub fn purge(&self) {
+        self.views.lock().unwrap().retain(|_, view| view.strong_count() > 0);
+    }
+
+    /// Number of views still alive
+    pub fn len(&self) -> usize {
+        self.views.lock().unwrap()
+            .values()
+            .filter(|view| view.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ImageViewCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pipeline cache persisted to disk, passed to every pipeline created by the crate.
+///
+/// The file is named after the device's pipeline cache UUID and driver version,
+/// and starts with both so a file written by another device or driver is discarded.
+pub struct PipelineCacheFile {
+    pub cache: Arc<PipelineCache>,
+    path: PathBuf,
+    header: Vec<u8>,
+}
+
+impl PipelineCacheFile {
+    /// Loads `dir/pipeline_cache_<uuid>_<driver version>.bin`, or starts empty
+    pub fn load(device: Arc<Device>, dir: impl AsRef<Path>) -> Self {
+        let properties = device.physical_device().properties();
+
+        let mut header = properties.pipeline_cache_uuid.to_vec();
+        header.extend_from_slice(&properties.driver_version.to_le_bytes());
+
+        let uuid = properties.pipeline_cache_uuid
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let path = dir.as_ref().join(format!("pipeline_cache_{uuid}_{}.bin", properties.driver_version));
+
+        let initial_data = match fs::read(&path) {
+            Ok(data) if data.starts_with(&header) => data[header.len()..].to_vec(),
+            _ => Vec::new(),
+        };
+
+        /* the header matched, so the data was written by `save` on this same device and driver.
+        the driver checks its own header as well and ignores anything it does not recognise */
+        let cache = unsafe {
+            PipelineCache::new(
+                device.clone(),
+                PipelineCacheCreateInfo {
+                    initial_data,
+                    ..Default::default()
+                },
+            )
+        }
+        .or_else(|_| unsafe { PipelineCache::new(device, Default::default()) })
+        .unwrap();
+
+        Self {
+            cache,
+            path,
+            header,
+        }
+    }
+
+    /// Default location, `<temp dir>/chaos-vk`
+    pub fn default_dir() -> PathBuf {
+        std::env::temp_dir().join("chaos-vk")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let data = self.cache.get_data().map_err(io::Error::other)?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = self.header.clone();
+        file.extend_from_slice(&data);
+
+        /* write then rename, so a crash mid write never leaves a truncated cache behind */
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, file)?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+impl Drop for PipelineCacheFile {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
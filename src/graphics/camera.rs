@@ -1,5 +1,7 @@
 use glam::{vec3, Mat4, Vec3};
 
+use super::culling::Frustum;
+
 const UP: Vec3 = Vec3::Y;
 const SENSITIVITY: f32 = 0.1; // todo: make this editable
 
@@ -205,5 +207,22 @@ impl Camera {
     pub fn get_proj(&self) -> [[f32; 4]; 4] {
         self.proj.to_cols_array_2d()
     }
+
+    /// Planes of what `proj * view` sees, for culling
+    pub fn frustum(&self) -> Frustum {
+        Frustum::new(self.proj * self.view)
+    }
+
+    /// Fraction of the screen height covered by a sphere, for level of detail selection
+    pub fn screen_size(&self, center: Vec3, radius: f32) -> f32 {
+        let scale = self.proj.y_axis.y.abs();
+
+        /* perspective projections divide by the distance */
+        if self.proj.w_axis.w == 0.0 {
+            scale * radius / center.distance(self.pos).max(f32::EPSILON)
+        } else {
+            scale * radius
+        }
+    }
  
  }
\ No newline at end of file
@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use ahash::HashMap;
+use vulkano::{buffer::{BufferContents, Subbuffer}, descriptor_set::WriteDescriptorSet, image::{sampler::Sampler, view::ImageView}, pipeline::{compute::ComputePipelineCreateInfo, layout::PipelineDescriptorSetLayoutCreateInfo, ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo}, shader::{ShaderModule, SpecializationConstant}};
+
+use super::{command::{submit_cmd_buf, BuilderType, VkBuilder}, utils::descriptor_set, vk::Vk};
+
+/// How much work a dispatch covers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Number of workgroups on each axis
+    Groups([u32; 3]),
+    /// Number of invocations on each axis, rounded up to whole workgroups.
+    /// The shader must discard the invocations out of bounds.
+    Invocations([u32; 3]),
+}
+
+impl Dispatch {
+    /// One dimensional invocation count, e.g. the length of a buffer
+    pub fn linear(invocations: u32) -> Self {
+        Dispatch::Invocations([invocations, 1, 1])
+    }
+}
+
+/// Descriptor writes for a dispatch, by binding.
+/// Everything goes to set 0 unless written with `write`.
+#[derive(Default)]
+pub struct ComputeBindings {
+    sets: BTreeMap<u32, Vec<WriteDescriptorSet>>,
+}
+
+impl ComputeBindings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Storage or uniform buffer
+    pub fn buffer<T: BufferContents + ?Sized>(self, binding: u32, buffer: Subbuffer<T>) -> Self {
+        self.write(0, WriteDescriptorSet::buffer(binding, buffer))
+    }
+
+    /// Storage image
+    pub fn image(self, binding: u32, view: Arc<ImageView>) -> Self {
+        self.write(0, WriteDescriptorSet::image_view(binding, view))
+    }
+
+    pub fn sampled_image(self, binding: u32, view: Arc<ImageView>, sampler: Arc<Sampler>) -> Self {
+        self.write(0, WriteDescriptorSet::image_view_sampler(binding, view, sampler))
+    }
+
+    pub fn write(mut self, set: u32, write: WriteDescriptorSet) -> Self {
+        self.sets.entry(set).or_default().push(write);
+        self
+    }
+}
+
+/// Specialization constants the workgroup size of `VkComputePipeline` shaders is read from.
+///
+/// vulkano can't give back the `local_size` of a module, so shaders dispatched with `Dispatch::Invocations`
+/// declare it with the ids as well:
+/// `layout(local_size_x = 8, local_size_y = 8, local_size_x_id = 100, local_size_y_id = 101) in;`.
+/// Axes without an id are 1. Other shaders can give it with `VkComputePipeline::with_local_size`.
+pub const LOCAL_SIZE_IDS: [u32; 3] = [100, 101, 102];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComputeError {
+    /// `Dispatch::Invocations` on a shader without `local_size_x_id`, see `LOCAL_SIZE_IDS`
+    UnknownLocalSize,
+    /// A `LOCAL_SIZE_IDS` constant that is not a uint
+    LocalSizeType(u32),
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputeError::UnknownLocalSize => write!(
+                f,
+                "the workgroup size is unknown, declare it with `local_size_x_id = {}` or use `with_local_size`",
+                LOCAL_SIZE_IDS[0],
+            ),
+            ComputeError::LocalSizeType(id) => write!(f, "the workgroup size constant {id} is not a uint"),
+        }
+    }
+}
+
+impl std::error::Error for ComputeError {}
+
+/// A compute pipeline together with the workgroup size of its shader
+#[derive(Clone)]
+pub struct VkComputePipeline {
+    pub pipeline: Arc<ComputePipeline>,
+    /// Read from the shader, see `LOCAL_SIZE_IDS`. Only needed by `Dispatch::Invocations`
+    local_size: Result<[u32; 3], ComputeError>,
+}
+
+impl VkComputePipeline {
+    pub fn new(vk: Arc<Vk>, cs: Arc<ShaderModule>) -> Self {
+        Self::with_specialization(vk, cs, [])
+    }
+
+    /// `constants` are `(constant_id, value)`, as in `layout(constant_id = 0) const uint N = 64;`.
+    /// The workgroup size can be specialized too, through `LOCAL_SIZE_IDS`.
+    pub fn with_specialization(
+        vk: Arc<Vk>,
+        cs: Arc<ShaderModule>,
+        constants: impl IntoIterator<Item = (u32, SpecializationConstant)>,
+    ) -> Self {
+        let constants = constants.into_iter().collect::<HashMap<_, _>>();
+        let local_size = local_size(&cs, &constants);
+
+        let entry_point = if constants.is_empty() {
+            cs.entry_point("main")
+        } else {
+            cs.specialize(constants).unwrap().entry_point("main")
+        }
+        .unwrap();
+
+        let stage = PipelineShaderStageCreateInfo::new(entry_point);
+
+        let layout = PipelineLayout::new(
+            vk.device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(vk.device.clone())
+                .unwrap(),
+        )
+        .unwrap();
+
+        let pipeline = ComputePipeline::new(
+            vk.device.clone(),
+            Some(vk.pipeline_cache.cache.clone()),
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )
+        .unwrap();
+
+        Self {
+            pipeline,
+            local_size,
+        }
+    }
+
+    /// Sets the workgroup size of a shader that doesn't declare `LOCAL_SIZE_IDS`
+    pub fn with_local_size(mut self, local_size: [u32; 3]) -> Self {
+        self.local_size = Ok(local_size);
+        self
+    }
+
+    pub fn layout(&self) -> &Arc<PipelineLayout> {
+        self.pipeline.layout()
+    }
+
+    pub fn local_size(&self) -> Result<[u32; 3], ComputeError> {
+        self.local_size.clone()
+    }
+
+    /// Workgroup counts covering `dispatch`. `Dispatch::Invocations` needs a known `local_size`
+    pub fn group_counts(&self, dispatch: Dispatch) -> Result<[u32; 3], ComputeError> {
+        match dispatch {
+            Dispatch::Groups(groups) => Ok(groups),
+            Dispatch::Invocations(invocations) => {
+                let local_size = self.local_size()?;
+
+                Ok([
+                    invocations[0].div_ceil(local_size[0]),
+                    invocations[1].div_ceil(local_size[1]),
+                    invocations[2].div_ceil(local_size[2]),
+                ])
+            }
+        }
+    }
+
+    /// Binds the pipeline and one descriptor set per set index in `bindings`
+    pub fn bind(&self, vk: Arc<Vk>, builder: &mut BuilderType, bindings: ComputeBindings) {
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .unwrap();
+
+        for (set, writes) in bindings.sets {
+            let descriptor_set = descriptor_set(vk.clone(), set as usize, self.pipeline.clone(), writes).0;
+
+            builder
+                .bind_descriptor_sets(PipelineBindPoint::Compute, self.layout().clone(), set, descriptor_set)
+                .unwrap();
+        }
+    }
+
+    pub fn push_constants<Pc: BufferContents>(&self, builder: &mut BuilderType, push_constants: Pc) {
+        builder
+            .push_constants(self.layout().clone(), 0, push_constants)
+            .unwrap();
+    }
+
+    /// Warning: this function assumes the pipeline has already been bound, see `bind`
+    pub fn dispatch(&self, builder: &mut BuilderType, dispatch: Dispatch) -> Result<(), ComputeError> {
+        builder
+            .dispatch(self.group_counts(dispatch)?)
+            .unwrap();
+
+        Ok(())
+    }
+
+    /// Records, submits and waits for a single dispatch
+    pub fn run(&self, vk: Arc<Vk>, bindings: ComputeBindings, dispatch: Dispatch) -> Result<(), ComputeError> {
+        let group_counts = self.group_counts(dispatch)?;
+
+        let mut builder = VkBuilder::new_once(vk.clone());
+        self.bind(vk.clone(), &mut builder.0, bindings);
+        self.dispatch(&mut builder.0, Dispatch::Groups(group_counts))?;
+
+        submit_cmd_buf(vk, builder.command_buffer())
+            .wait(None)
+            .unwrap();
+
+        Ok(())
+    }
+
+    /// `run`, then reads `output` back. `output` must be host visible, like `VkIterBuffer::storage`
+    pub fn run_and_read<T: BufferContents + Clone>(
+        &self,
+        vk: Arc<Vk>,
+        bindings: ComputeBindings,
+        dispatch: Dispatch,
+        output: &Subbuffer<[T]>,
+    ) -> Result<Vec<T>, ComputeError> {
+        self.run(vk, bindings, dispatch)?;
+
+        Ok(output.read().unwrap().to_vec())
+    }
+}
+
+/// Workgroup size from the `LOCAL_SIZE_IDS` constants of `cs`, specialized by `constants`
+fn local_size(cs: &ShaderModule, constants: &HashMap<u32, SpecializationConstant>) -> Result<[u32; 3], ComputeError> {
+    let defaults = cs.specialization_constants();
+    if !defaults.contains_key(&LOCAL_SIZE_IDS[0]) {
+        return Err(ComputeError::UnknownLocalSize);
+    }
+
+    let mut local_size = [1; 3];
+    for (size, id) in local_size.iter_mut().zip(LOCAL_SIZE_IDS) {
+        match constants.get(&id).or_else(|| defaults.get(&id)) {
+            Some(SpecializationConstant::U32(value)) => *size = *value,
+            Some(_) => return Err(ComputeError::LocalSizeType(id)),
+            None => {}
+        }
+    }
+
+    Ok(local_size)
+}
@@ -0,0 +1,289 @@
+use std::{mem::size_of, sync::Arc};
+
+use glam::{Mat4, Vec3, Vec4};
+use vulkano::{buffer::BufferContents, command_buffer::DrawIndexedIndirectCommand, pipeline::GraphicsPipeline};
+
+use super::{buffer::VkIterBuffer, command::BuilderType, compute::{ComputeBindings, Dispatch, VkComputePipeline}, mesh::mesh::Mesh, reflect::BindError, stats::FrameStats, vertex::{ModelInstanceData, PosInstanceData}, vk::Vk};
+
+pub mod shaders;
+
+/// Planes of the volume seen by `view_proj` (projection * view) as normal and distance,
+/// normals pointing inside: left, right, bottom, top, near, far
+pub fn frustum_planes(view_proj: Mat4) -> [Vec4; 6] {
+    let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+
+    /* depth goes from 0 to 1 */
+    [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+        .map(|plane| plane / plane.truncate().length())
+}
+
+/// Sphere `sphere` (center and radius) moved by `transform`, scaled by its biggest axis
+pub fn transform_sphere(transform: Mat4, sphere: Vec4) -> Vec4 {
+    let center = transform.transform_point3(sphere.truncate());
+    let scale = transform.x_axis.truncate().length()
+        .max(transform.y_axis.truncate().length())
+        .max(transform.z_axis.truncate().length());
+
+    center.extend(sphere.w * scale)
+}
+
+/// See `frustum_planes` and `Camera::frustum`
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn new(view_proj: Mat4) -> Self {
+        Self {
+            planes: frustum_planes(view_proj),
+        }
+    }
+
+    /// False only when the sphere (center and radius) is fully outside
+    pub fn intersects_sphere(&self, sphere: Vec4) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.truncate()) + plane.w >= -sphere.w)
+    }
+
+    /// False only when the box is fully outside one of the planes
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            /* corner furthest along the normal */
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+/// Axis aligned box and sphere around a mesh and all its instances, see `Mesh::bounds`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Center and radius
+    pub sphere: Vec4,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min: Vec3::ZERO,
+            max: Vec3::ZERO,
+            sphere: Vec4::ZERO,
+        }
+    }
+}
+
+impl Bounds {
+    /// Bounds of a box with its corners placed by `transforms`, e.g. a mesh and its instances
+    pub fn from_instances(min: Vec3, max: Vec3, sphere: Vec4, transforms: impl IntoIterator<Item = Mat4>) -> Self {
+        let mut bounds_min = Vec3::MAX;
+        let mut bounds_max = Vec3::MIN;
+        let mut spheres = vec![];
+
+        for transform in transforms {
+            let (corner_min, corner_max) = transform_aabb(transform, min, max);
+            bounds_min = bounds_min.min(corner_min);
+            bounds_max = bounds_max.max(corner_max);
+
+            spheres.push(transform_sphere(transform, sphere));
+        }
+
+        if spheres.is_empty() {
+            return Self::default();
+        }
+
+        let center = (bounds_min + bounds_max) * 0.5;
+        let radius = spheres
+            .iter()
+            .map(|sphere| sphere.truncate().distance(center) + sphere.w)
+            .fold(0.0, f32::max);
+
+        Self {
+            min: bounds_min,
+            max: bounds_max,
+            sphere: center.extend(radius),
+        }
+    }
+
+    /// Bounds after `transform`, still axis aligned so they may grow
+    pub fn transform(&self, transform: Mat4) -> Self {
+        let (min, max) = transform_aabb(transform, self.min, self.max);
+
+        Self {
+            min,
+            max,
+            sphere: transform_sphere(transform, self.sphere),
+        }
+    }
+
+    pub fn is_visible(&self, frustum: &Frustum) -> bool {
+        frustum.intersects_sphere(self.sphere) && frustum.intersects_aabb(self.min, self.max)
+    }
+}
+
+/// Box around the 8 transformed corners of `min..max`
+pub fn transform_aabb(transform: Mat4, min: Vec3, max: Vec3) -> (Vec3, Vec3) {
+    (0..8)
+        .map(|i| transform.transform_point3(Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )))
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), corner| (min.min(corner), max.max(corner)))
+}
+
+/// Meshes with some of their bounds inside `frustum`, counted in `stats`
+pub fn visible_meshes<'a>(meshes: impl IntoIterator<Item = &'a Mesh>, frustum: &Frustum, stats: &mut FrameStats) -> Vec<&'a Mesh> {
+    let mut visible = vec![];
+
+    for mesh in meshes {
+        stats.meshes += 1;
+
+        if mesh.is_visible(frustum) {
+            visible.push(mesh);
+        } else {
+            stats.culled_meshes += 1;
+        }
+    }
+
+    visible
+}
+
+/// Instances of one mesh with their world space bounding spheres, culled on the GPU by `GpuCulling`.
+/// `output` holds the visible instances, packed, and `indirect` the draw with their count.
+pub struct CulledInstances<T: BufferContents> {
+    pub count: u32,
+    pub index_count: u32,
+
+    pub spheres: VkIterBuffer<[f32; 4]>,
+    pub input: VkIterBuffer<T>,
+    pub output: VkIterBuffer<T>,
+    pub indirect: VkIterBuffer<DrawIndexedIndirectCommand>,
+}
+
+impl<T: BufferContents + Copy> CulledInstances<T> {
+    /// `spheres` (center and radius) match `instances` one to one, `index_count` is the index count of the mesh
+    pub fn new(vk: Arc<Vk>, instances: Vec<T>, spheres: Vec<Vec4>, index_count: u32) -> Self {
+        assert_eq!(instances.len(), spheres.len(), "one bounding sphere per instance");
+        assert!(size_of::<T>().is_multiple_of(4), "instances are copied as 32 bit words");
+
+        let count = instances.len() as u32;
+
+        Self {
+            count,
+            index_count,
+
+            spheres: VkIterBuffer::storage(vk.allocators.clone(), spheres.into_iter().map(|sphere| sphere.to_array())),
+            input: VkIterBuffer::storage(vk.allocators.clone(), instances.into_iter()),
+            output: VkIterBuffer::vertex_storage(vk.allocators.clone(), count.max(1) as u64),
+            indirect: VkIterBuffer::indirect_storage(vk.allocators.clone(), [DrawIndexedIndirectCommand::default()].into_iter()),
+        }
+    }
+
+    /// For moving instances, the instance data itself is not updated
+    pub fn set_spheres(&mut self, vk: Arc<Vk>, spheres: Vec<Vec4>) {
+        assert_eq!(spheres.len(), self.count as usize, "one bounding sphere per instance");
+
+        self.spheres = VkIterBuffer::storage(vk.allocators.clone(), spheres.into_iter().map(|sphere| sphere.to_array()));
+    }
+
+    /// Draws the visible instances of `mesh`, after `GpuCulling::build_commands` for this frame.
+    /// `pipeline` must take `T` at binding 1 and already be bound.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, mesh: &Mesh, pipeline: Arc<GraphicsPipeline>) -> Result<(), BindError> {
+        mesh.build_commands_indirect(vk, builder, pipeline, self.output.content.clone(), self.indirect.content.clone())
+    }
+}
+
+impl CulledInstances<PosInstanceData> {
+    /// Offsets of `mesh.instances`, with spheres placed by the current mesh transform
+    pub fn from_mesh(vk: Arc<Vk>, mesh: &Mesh) -> Self {
+        let model = Mat4::from_cols_array_2d(&mesh.get_model());
+        let sphere = mesh.bounding_sphere();
+
+        let spheres = mesh.instances
+            .iter()
+            .map(|instance| transform_sphere(model, (sphere.truncate() + Vec3::from(instance.ofs)).extend(sphere.w)))
+            .collect();
+
+        Self::new(vk, mesh.instances.clone(), spheres, mesh.indices.len() as u32)
+    }
+}
+
+impl CulledInstances<ModelInstanceData> {
+    /// `mesh.model_instances`, with spheres placed by the current mesh transform
+    pub fn from_mesh_transforms(vk: Arc<Vk>, mesh: &Mesh) -> Self {
+        let model = Mat4::from_cols_array_2d(&mesh.get_model());
+        let sphere = mesh.bounding_sphere();
+
+        let spheres = mesh.model_instances
+            .iter()
+            .map(|instance| transform_sphere(model * Mat4::from_cols_array_2d(&instance.instance_model), sphere))
+            .collect();
+
+        Self::new(vk, mesh.model_instances.clone(), spheres, mesh.indices.len() as u32)
+    }
+}
+
+/// Compute pass frustum culling `CulledInstances` and writing their indirect draws,
+/// so drawing them costs no CPU work per instance.
+///
+/// ```ignore
+/// let culling = GpuCulling::new(vk.clone());
+/// let trees = CulledInstances::from_mesh_transforms(vk.clone(), &tree);
+///
+/// // outside of the render pass
+/// culling.build_commands(vk.clone(), &mut builder, &trees, camera.proj * camera.view);
+/// // inside, with a pipeline taking `ModelInstanceData`
+/// trees.build_commands(vk.clone(), &mut builder, &tree, pipeline.clone())?;
+/// ```
+pub struct GpuCulling {
+    pub pipeline: VkComputePipeline,
+}
+
+impl GpuCulling {
+    pub fn new(vk: Arc<Vk>) -> Self {
+        let cs = shaders::cs::load(vk.device.clone()).unwrap();
+
+        Self {
+            pipeline: VkComputePipeline::new(vk, cs),
+        }
+    }
+
+    /// Resets the draw of `instances` and culls them against `view_proj`. Must be recorded outside of a render pass.
+    pub fn build_commands<T: BufferContents>(&self, vk: Arc<Vk>, builder: &mut BuilderType, instances: &CulledInstances<T>, view_proj: Mat4) {
+        let draw = DrawIndexedIndirectCommand {
+            index_count: instances.index_count,
+            instance_count: 0,
+            first_index: 0,
+            vertex_offset: 0,
+            first_instance: 0,
+        };
+
+        builder
+            .update_buffer(instances.indirect.content.clone(), vec![draw].into_boxed_slice())
+            .unwrap();
+
+        if instances.count == 0 {
+            return;
+        }
+
+        let bindings = ComputeBindings::new()
+            .buffer(0, instances.spheres.content.clone())
+            .buffer(1, instances.input.content.clone())
+            .buffer(2, instances.output.content.clone())
+            .buffer(3, instances.indirect.content.clone());
+
+        self.pipeline.bind(vk, builder, bindings);
+        self.pipeline.push_constants(builder, shaders::cs::CullPC {
+            planes: frustum_planes(view_proj).map(|plane| plane.to_array()),
+            count: instances.count,
+            stride: (size_of::<T>() / 4) as u32,
+        });
+        self.pipeline.dispatch(builder, Dispatch::linear(instances.count)).unwrap();
+    }
+}
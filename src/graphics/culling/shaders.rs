@@ -0,0 +1,59 @@
+/// Frustum culls one sphere per instance and appends the visible instances to `instances_out`,
+/// counting them in the `instance_count` of the indirect draw
+pub mod cs {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 64, local_size_x_id = 100) in;
+
+            layout(set = 0, binding = 0) readonly buffer Spheres {
+                vec4 spheres[]; // center, radius
+            };
+
+            // instances are copied as words, `stride` per instance
+            layout(set = 0, binding = 1) readonly buffer InstancesIn {
+                uint instances_in[];
+            };
+
+            layout(set = 0, binding = 2) writeonly buffer InstancesOut {
+                uint instances_out[];
+            };
+
+            // DrawIndexedIndirectCommand
+            layout(set = 0, binding = 3) buffer Draw {
+                uint index_count;
+                uint instance_count;
+                uint first_index;
+                int vertex_offset;
+                uint first_instance;
+            };
+
+            layout(push_constant) uniform CullPC {
+                vec4 planes[6];
+                uint count;
+                uint stride;
+            };
+
+            void main() {
+                uint i = gl_GlobalInvocationID.x;
+                if (i >= count) {
+                    return;
+                }
+
+                vec4 sphere = spheres[i];
+                for (int p = 0; p < 6; p++) {
+                    if (dot(planes[p].xyz, sphere.xyz) + planes[p].w < -sphere.w) {
+                        return;
+                    }
+                }
+
+                uint slot = atomicAdd(instance_count, 1);
+                for (uint w = 0; w < stride; w++) {
+                    instances_out[slot * stride + w] = instances_in[i * stride + w];
+                }
+            }
+        ",
+    }
+}
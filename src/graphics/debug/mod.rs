@@ -0,0 +1,301 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use glam::{Mat4, Vec3, Vec4};
+use imgui::Ui;
+use vulkano::{buffer::BufferContents, descriptor_set::WriteDescriptorSet, pipeline::{graphics::{input_assembly::PrimitiveTopology, vertex_input::Vertex, viewport::Viewport}, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::RenderPass};
+
+use super::{buffer::{VkBuffer, VkIterBuffer}, camera::Camera, command::BuilderType, culling::transform_sphere, mesh::mesh::Mesh, pipeline::{BlendPreset, GraphicsPipelineBuilder}, utils::descriptor_set, vk::Vk};
+
+pub mod shaders;
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Color of each level of detail in `DebugDraw::mesh_lods`, the last one repeats
+const LOD_COLORS: [[f32; 4]; 5] = [
+    [0.2, 1.0, 0.2, 1.0],
+    [1.0, 1.0, 0.2, 1.0],
+    [1.0, 0.6, 0.1, 1.0],
+    [1.0, 0.2, 0.2, 1.0],
+    [0.8, 0.2, 1.0, 1.0],
+];
+
+#[derive(BufferContents, Vertex, Copy, Clone)]
+#[repr(C)]
+pub struct DebugVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub pos: [f32; 3],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+struct DebugText {
+    pos: Vec3,
+    color: Vec4,
+    text: String,
+}
+
+/// Immediate-mode lines for debugging: add primitives during the frame, draw them with
+/// `build_commands` inside a render pass with depth, then `clear` before the next frame.
+///
+/// Primitives added while `depth_test` is false are drawn on top of everything.
+///
+/// ```ignore
+/// debug.clear();
+/// debug.grid(Vec3::ZERO, 50.0, 50, Vec4::splat(0.3));
+/// debug.depth_test = false;
+/// debug.arrow(a, b, Vec4::new(1.0, 0.0, 0.0, 1.0));
+/// debug.text_3d(b, Vec4::ONE, "velocity");
+///
+/// // inside the scene render pass
+/// debug.build_commands(vk.clone(), &mut builder, &camera, viewport);
+/// // with the ImGui frame
+/// debug.draw_text(ui, &camera);
+/// ```
+pub struct DebugDraw {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub overlay_pipeline: Arc<GraphicsPipeline>,
+    /// Applies to the primitives added afterwards
+    pub depth_test: bool,
+
+    lines: Vec<DebugVertex>,
+    overlay_lines: Vec<DebugVertex>,
+    texts: Vec<DebugText>,
+}
+
+impl DebugDraw {
+    /// `render_pass` must have a depth attachment, like `render_pass_with_depth`
+    pub fn new(vk: Arc<Vk>, render_pass: Arc<RenderPass>) -> Self {
+        let vs = shaders::vs::load(vk.device.clone()).unwrap();
+        let fs = shaders::fs::load(vk.device.clone()).unwrap();
+
+        let builder = GraphicsPipelineBuilder::new(vs, fs)
+            .vertex::<DebugVertex>()
+            .topology(PrimitiveTopology::LineList)
+            .blend(BlendPreset::Alpha)
+            .dynamic_state(DynamicState::Viewport);
+
+        Self {
+            pipeline: builder.clone()
+                .depth_write(false)
+                .build(vk.clone(), render_pass.clone()),
+            overlay_pipeline: builder
+                .depth_test(false)
+                .build(vk, render_pass),
+            depth_test: true,
+
+            lines: vec![],
+            overlay_lines: vec![],
+            texts: vec![],
+        }
+    }
+
+    /// Removes every primitive
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.overlay_lines.clear();
+        self.texts.clear();
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Vec4) {
+        let lines = if self.depth_test { &mut self.lines } else { &mut self.overlay_lines };
+
+        lines.push(DebugVertex { pos: a.to_array(), color: color.to_array() });
+        lines.push(DebugVertex { pos: b.to_array(), color: color.to_array() });
+    }
+
+    /// Red, green and blue lines along the x, y and z axes of `transform`
+    pub fn axes(&mut self, transform: Mat4, size: f32) {
+        let origin = transform.transform_point3(Vec3::ZERO);
+
+        self.line(origin, transform.transform_point3(Vec3::X * size), Vec4::new(1.0, 0.0, 0.0, 1.0));
+        self.line(origin, transform.transform_point3(Vec3::Y * size), Vec4::new(0.0, 1.0, 0.0, 1.0));
+        self.line(origin, transform.transform_point3(Vec3::Z * size), Vec4::new(0.0, 0.0, 1.0, 1.0));
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec4) {
+        let corner = |i: usize| Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+
+        self.box_edges(std::array::from_fn(corner), color);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec4) {
+        let (u, v) = normal.normalize().any_orthonormal_pair();
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Three great circles
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        self.circle(center, Vec3::X, radius, color);
+        self.circle(center, Vec3::Y, radius, color);
+        self.circle(center, Vec3::Z, radius, color);
+    }
+
+    /// Line from `from` to `to` with a head a fifth of its length
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: Vec4) {
+        self.line(from, to, color);
+
+        let dir = to - from;
+        let length = dir.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        let (u, v) = (dir / length).any_orthonormal_pair();
+        let base = to - dir * 0.2;
+        let width = length * 0.07;
+
+        for side in [u, -u, v, -v] {
+            self.line(to, base + side * width, color);
+        }
+    }
+
+    /// Square grid on the xz plane, `half_size` around `center` with `divisions` cells per side
+    pub fn grid(&mut self, center: Vec3, half_size: f32, divisions: u32, color: Vec4) {
+        let divisions = divisions.max(1);
+        let step = 2.0 * half_size / divisions as f32;
+
+        for i in 0..=divisions {
+            let t = -half_size + i as f32 * step;
+
+            self.line(center + Vec3::new(t, 0.0, -half_size), center + Vec3::new(t, 0.0, half_size), color);
+            self.line(center + Vec3::new(-half_size, 0.0, t), center + Vec3::new(half_size, 0.0, t), color);
+        }
+    }
+
+    /// Edges of the volume seen by `view_proj` (projection * view), e.g. a camera or a shadow cascade
+    pub fn frustum(&mut self, view_proj: Mat4, color: Vec4) {
+        let inverse = view_proj.inverse();
+        let corner = |i: usize| inverse.project_point3(Vec3::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { 0.0 } else { 1.0 },
+        ));
+
+        self.box_edges(std::array::from_fn(corner), color);
+    }
+
+    /// Bounding spheres of `mesh` colored by their level of detail, green being the finest.
+    /// One per instance after `Mesh::select_instance_lods`, else one for the whole mesh with its level as a label.
+    pub fn mesh_lods(&mut self, mesh: &Mesh) {
+        let color = |level: usize| Vec4::from(LOD_COLORS[level.min(LOD_COLORS.len() - 1)]);
+        let model = Mat4::from_cols_array_2d(&mesh.get_model());
+
+        if mesh.lods.groups.is_empty() {
+            let sphere = mesh.world_bounds().sphere;
+
+            self.sphere(sphere.truncate(), sphere.w, color(mesh.lods.current));
+            self.text_3d(sphere.truncate(), color(mesh.lods.current), format!("lod {}", mesh.lods.current));
+            return;
+        }
+
+        let sphere = mesh.bounding_sphere();
+        for (level, transform) in mesh.lods.instance_levels.iter().zip(mesh.instance_transforms()) {
+            let world = transform_sphere(model * transform, sphere);
+
+            self.sphere(world.truncate(), world.w, color(*level));
+        }
+    }
+
+    /// Drawn by `draw_text`, on top of everything
+    pub fn text_3d(&mut self, pos: Vec3, color: Vec4, text: impl Into<String>) {
+        self.texts.push(DebugText {
+            pos,
+            color,
+            text: text.into(),
+        });
+    }
+
+    /// Number of lines to draw
+    pub fn len(&self) -> usize {
+        (self.lines.len() + self.overlay_lines.len()) / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.overlay_lines.is_empty()
+    }
+
+    /// Uploads every line into one vertex buffer and draws it. Must be recorded inside the render pass given to `new`.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, camera: &Camera, viewport: Viewport) {
+        if self.is_empty() {
+            return;
+        }
+
+        let vertices = self.lines
+            .iter()
+            .chain(self.overlay_lines.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        let vbo = VkIterBuffer::vertex(vk.allocators.clone(), vertices);
+
+        let ubo = VkBuffer::uniform(vk.allocators.clone(), shaders::vs::Camera {
+            view: camera.get_view(),
+            proj: camera.get_proj(),
+        });
+
+        let depth_tested = self.lines.len() as u32;
+        let overlay = self.overlay_lines.len() as u32;
+
+        builder
+            .bind_vertex_buffers(0, vbo.content.clone())
+            .unwrap();
+
+        for (pipeline, count, first) in [(&self.pipeline, depth_tested, 0), (&self.overlay_pipeline, overlay, depth_tested)] {
+            if count == 0 {
+                continue;
+            }
+
+            let set = descriptor_set(vk.clone(), 0, pipeline.clone(), [WriteDescriptorSet::buffer(0, ubo.content.clone())]).0;
+
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .unwrap()
+                .set_viewport(0, [viewport.clone()].into_iter().collect())
+                .unwrap()
+                .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set)
+                .unwrap()
+                .draw(count, 1, first, 0)
+                .unwrap();
+        }
+    }
+
+    /// Draws the `text_3d` labels on the ImGui foreground draw list
+    pub fn draw_text(&self, ui: &Ui, camera: &Camera) {
+        let view_proj = camera.proj * camera.view;
+        let [width, height] = ui.io().display_size;
+        let draw_list = ui.get_foreground_draw_list();
+
+        for text in &self.texts {
+            let clip = view_proj * text.pos.extend(1.0);
+            if clip.w <= 0.0 {
+                continue;
+            }
+
+            let ndc = clip.truncate() / clip.w;
+            let screen = [(ndc.x + 1.0) * 0.5 * width, (ndc.y + 1.0) * 0.5 * height];
+
+            draw_list.add_text(screen, text.color.to_array(), &text.text);
+        }
+    }
+
+    /// 12 edges between corners indexed by their x, y and z bits
+    fn box_edges(&mut self, corners: [Vec3; 8], color: Vec4) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+}
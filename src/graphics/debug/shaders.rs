@@ -0,0 +1,40 @@
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 pos;
+            layout(location = 1) in vec4 color;
+
+            layout(set = 0, binding = 0) uniform Camera {
+                mat4 view;
+                mat4 proj;
+            };
+
+            layout(location = 0) out vec4 v_color;
+
+            void main() {
+                gl_Position = proj * view * vec4(pos, 1.0);
+                v_color = color;
+            }
+        ",
+    }
+}
+
+pub mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = v_color;
+            }
+        ",
+    }
+}
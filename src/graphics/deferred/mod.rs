@@ -0,0 +1,388 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+use vulkano::{buffer::BufferContents, command_buffer::{RenderPassBeginInfo, SubpassBeginInfo, SubpassContents, SubpassEndInfo}, descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, format::{ClearValue, Format}, image::{view::ImageView, Image, ImageCreateInfo, ImageUsage}, memory::allocator::AllocationCreateInfo, pipeline::{graphics::viewport::Viewport, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}};
+
+use super::{buffer::{VkBuffer, VkIterBuffer}, camera::Camera, command::BuilderType, mesh::mesh::Mesh, pipeline::GraphicsPipelineBuilder, reflect::BindError, utils::descriptor_set, vertex::{ModelInstanceData, PosInstanceData, PosVertex}, vk::Vk};
+
+pub mod shaders;
+
+pub const ALBEDO_FORMAT: Format = Format::R8G8B8A8_UNORM;
+pub const NORMAL_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+/// World position in xyz, view space depth in w
+pub const POSITION_FORMAT: Format = Format::R32G32B32A32_SFLOAT;
+pub const MATERIAL_FORMAT: Format = Format::R32_UINT;
+pub const DEPTH_FORMAT: Format = Format::D32_SFLOAT;
+
+/// What the lighting subpass writes to the swapchain image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum GBufferView {
+    #[default]
+    Lit,
+    Albedo,
+    Normal,
+    Position,
+    Depth,
+    /// Each material id gets its own color
+    Material,
+}
+
+impl GBufferView {
+    pub const ALL: [GBufferView; 6] = [
+        GBufferView::Lit,
+        GBufferView::Albedo,
+        GBufferView::Normal,
+        GBufferView::Position,
+        GBufferView::Depth,
+        GBufferView::Material,
+    ];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Distance at which the light fades out completely
+    pub radius: f32,
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+/// std430 layout of `Light` in the lighting shader
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuLight {
+    position_radius: [f32; 4],
+    color_intensity: [f32; 4],
+}
+
+/// Opt-in deferred path: a geometry subpass fills the G-buffer
+/// (albedo, normal, position/depth and material id), then a lighting subpass
+/// reads it back as input attachments and shades every light in `lights`.
+///
+/// Uses its own render pass, draw ImGui or anything else with a separate pass afterwards.
+pub struct DeferredRenderer {
+    pub render_pass: Arc<RenderPass>,
+    pub geometry_pipeline: Arc<GraphicsPipeline>,
+    /// `geometry_pipeline` for meshes with `Mesh::set_instance_transforms`
+    pub instanced_geometry_pipeline: Arc<GraphicsPipeline>,
+    pub lighting_pipeline: Arc<GraphicsPipeline>,
+    pub framebuffers: Vec<Arc<Framebuffer>>,
+    gbuffer_set: Option<Arc<PersistentDescriptorSet>>,
+
+    pub lights: Vec<PointLight>,
+    pub ambient: Vec3,
+    pub background: [f32; 4],
+    pub view: GBufferView,
+}
+
+impl DeferredRenderer {
+    /// `format` is the swapchain image format. Call `resize` before drawing.
+    pub fn new(vk: Arc<Vk>, format: Format) -> Self {
+        let render_pass = vulkano::ordered_passes_renderpass!(vk.device.clone(),
+            attachments: {
+                final_color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                albedo: {
+                    format: ALBEDO_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                normal: {
+                    format: NORMAL_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                position: {
+                    format: POSITION_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                material: {
+                    format: MATERIAL_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                }
+            },
+            passes: [
+                {
+                    color: [albedo, normal, position, material],
+                    depth_stencil: {depth},
+                    input: []
+                },
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: [albedo, normal, position, material]
+                }
+            ]
+        )
+        .unwrap();
+
+        let geometry_pipeline = GraphicsPipelineBuilder::new(
+            shaders::gvs::load(vk.device.clone()).unwrap(),
+            shaders::gfs::load(vk.device.clone()).unwrap(),
+        )
+            .vertex::<PosVertex>()
+            .instance::<PosInstanceData>()
+            .dynamic_state(DynamicState::Viewport)
+            .subpass(0)
+            .reflect(shaders::gvs::SOURCE, shaders::gfs::SOURCE)
+            .build(vk.clone(), render_pass.clone());
+        let instanced_geometry_pipeline = GraphicsPipelineBuilder::new(
+            shaders::instanced_gvs::load(vk.device.clone()).unwrap(),
+            shaders::gfs::load(vk.device.clone()).unwrap(),
+        )
+            .vertex::<PosVertex>()
+            .instance::<ModelInstanceData>()
+            .dynamic_state(DynamicState::Viewport)
+            .subpass(0)
+            .reflect(shaders::instanced_gvs::SOURCE, shaders::gfs::SOURCE)
+            .build(vk.clone(), render_pass.clone());
+
+        let lighting_pipeline = GraphicsPipelineBuilder::new(
+            shaders::lvs::load(vk.device.clone()).unwrap(),
+            shaders::lfs::load(vk.device.clone()).unwrap(),
+        )
+            .dynamic_state(DynamicState::Viewport)
+            .subpass(1)
+            .build(vk.clone(), render_pass.clone());
+
+        Self {
+            render_pass,
+            geometry_pipeline,
+            instanced_geometry_pipeline,
+            lighting_pipeline,
+            framebuffers: vec![],
+            gbuffer_set: None,
+
+            lights: vec![],
+            ambient: Vec3::splat(0.05),
+            background: [0.1, 0.2, 0.3, 1.0],
+            view: GBufferView::Lit,
+        }
+    }
+
+    /// (Re)creates the G-buffer and one framebuffer per swapchain image
+    pub fn resize(&mut self, vk: Arc<Vk>, images: &[Arc<Image>]) {
+        let extent = images[0].extent();
+
+        let albedo = gbuffer_attachment(vk.clone(), ALBEDO_FORMAT, extent, ImageUsage::INPUT_ATTACHMENT);
+        let normal = gbuffer_attachment(vk.clone(), NORMAL_FORMAT, extent, ImageUsage::INPUT_ATTACHMENT);
+        let position = gbuffer_attachment(vk.clone(), POSITION_FORMAT, extent, ImageUsage::INPUT_ATTACHMENT);
+        let material = gbuffer_attachment(vk.clone(), MATERIAL_FORMAT, extent, ImageUsage::INPUT_ATTACHMENT);
+        let depth = gbuffer_attachment(vk.clone(), DEPTH_FORMAT, extent, ImageUsage::empty());
+
+        self.framebuffers = images
+            .iter()
+            .map(|image| {
+                Framebuffer::new(
+                    self.render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![
+                            vk.image_views.get_default(image),
+                            albedo.clone(),
+                            normal.clone(),
+                            position.clone(),
+                            material.clone(),
+                            depth.clone(),
+                        ],
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+
+        self.gbuffer_set = Some(descriptor_set(
+            vk,
+            0,
+            self.lighting_pipeline.clone(),
+            [
+                WriteDescriptorSet::image_view(0, albedo),
+                WriteDescriptorSet::image_view(1, normal),
+                WriteDescriptorSet::image_view(2, position),
+                WriteDescriptorSet::image_view(3, material),
+            ],
+        ).0);
+    }
+
+    /// In framebuffer attachment order
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        vec![
+            Some(self.background.into()),
+            Some([0.0; 4].into()),
+            Some([0.0; 4].into()),
+            Some([0.0; 4].into()),
+            Some(ClearValue::Uint([0; 4])),
+            Some(1.0.into()),
+        ]
+    }
+
+    /// Records the whole render pass into `framebuffers[image_i]`:
+    /// every mesh with its `color` as albedo and the given material id, then the lights.
+    /// Meshes with `Mesh::set_instance_transforms` go through `instanced_geometry_pipeline`.
+    pub fn build_commands<'a>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        image_i: usize,
+        camera: &Camera,
+        meshes: impl IntoIterator<Item = (&'a Mesh, u32)>,
+    ) -> Result<(), BindError> {
+        let framebuffer = self.framebuffers[image_i].clone();
+        let extent = framebuffer.extent();
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..=1.0,
+        };
+
+        let camera_ubo = VkBuffer::uniform(vk.allocators.clone(), shaders::gvs::Camera {
+            view: camera.get_view(),
+            proj: camera.get_proj(),
+        });
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: self.clear_values(),
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .set_viewport(0, [viewport.clone()].into_iter().collect())
+            .unwrap();
+
+        /* a mesh that fails to bind is skipped, the first error is returned once the pass is closed */
+        let mut result = Ok(());
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for (mesh, material) in meshes {
+            let pipeline = if mesh.tbo.is_some() { &self.instanced_geometry_pipeline } else { &self.geometry_pipeline };
+
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                let camera_set = descriptor_set(
+                    vk.clone(),
+                    0,
+                    pipeline.clone(),
+                    [WriteDescriptorSet::buffer(0, camera_ubo.content.clone())],
+                ).0;
+
+                builder
+                    .bind_pipeline_graphics(pipeline.clone())
+                    .unwrap()
+                    .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, camera_set)
+                    .unwrap();
+                bound = Some(pipeline);
+            }
+
+            builder
+                .push_constants(pipeline.layout().clone(), 0, shaders::gfs::GeometryPC {
+                    albedo: mesh.color.extend(1.0).to_array(),
+                    material,
+                })
+                .unwrap();
+
+            result = result.and(mesh.build_commands(vk.clone(), builder, pipeline.clone()));
+        }
+
+        /* lights, with a dummy one so the storage buffer is never empty */
+        let mut lights = self.lights
+            .iter()
+            .map(|light| GpuLight {
+                position_radius: light.position.extend(light.radius).to_array(),
+                color_intensity: light.color.extend(light.intensity).to_array(),
+            })
+            .collect::<Vec<_>>();
+        let light_count = lights.len() as u32;
+        if lights.is_empty() {
+            lights.push(GpuLight {
+                position_radius: [0.0; 4],
+                color_intensity: [0.0; 4],
+            });
+        }
+
+        let light_buffer = VkIterBuffer::storage(vk.allocators.clone(), lights.into_iter());
+        let light_set = descriptor_set(
+            vk.clone(),
+            1,
+            self.lighting_pipeline.clone(),
+            [WriteDescriptorSet::buffer(0, light_buffer.content.clone())],
+        ).0;
+
+        builder
+            .next_subpass(
+                SubpassEndInfo::default(),
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(self.lighting_pipeline.clone())
+            .unwrap()
+            .set_viewport(0, [viewport].into_iter().collect())
+            .unwrap()
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.lighting_pipeline.layout().clone(),
+                0,
+                (self.gbuffer_set.clone().expect("call resize before drawing"), light_set),
+            )
+            .unwrap()
+            .push_constants(self.lighting_pipeline.layout().clone(), 0, shaders::lfs::LightingPC {
+                camera_pos: camera.pos.extend(1.0).to_array(),
+                ambient: self.ambient.extend(1.0).to_array(),
+                background: self.background,
+                light_count,
+                debug_view: self.view as u32,
+            })
+            .unwrap()
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass(SubpassEndInfo::default())
+            .unwrap();
+
+        result
+    }
+}
+
+fn gbuffer_attachment(vk: Arc<Vk>, format: Format, extent: [u32; 3], usage: ImageUsage) -> Arc<ImageView> {
+    let usage = usage | if format == DEPTH_FORMAT {
+        ImageUsage::DEPTH_STENCIL_ATTACHMENT
+    } else {
+        ImageUsage::COLOR_ATTACHMENT
+    };
+
+    ImageView::new_default(
+        Image::new(
+            vk.allocators.memory.clone(),
+            ImageCreateInfo {
+                format,
+                extent,
+                usage: usage | ImageUsage::TRANSIENT_ATTACHMENT,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
@@ -0,0 +1,126 @@
+/// Same inputs and sets as the usual `Mesh` shaders
+pub mod gvs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/gbuffer.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/gbuffer.vert");
+}
+
+/// `gvs` for meshes with `Mesh::set_instance_transforms`
+pub mod instanced_gvs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/gbuffer_instanced.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/gbuffer_instanced.vert");
+}
+
+pub mod gfs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        path: "src/graphics/shaders/gbuffer.frag",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/gbuffer.frag");
+}
+
+pub mod lvs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            void main() {
+                // single triangle covering the screen
+                vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod lfs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_albedo;
+            layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput u_normal;
+            layout(input_attachment_index = 2, set = 0, binding = 2) uniform subpassInput u_position;
+            layout(input_attachment_index = 3, set = 0, binding = 3) uniform usubpassInput u_material;
+
+            struct Light {
+                vec4 position_radius;
+                vec4 color_intensity;
+            };
+
+            layout(set = 1, binding = 0) readonly buffer Lights {
+                Light lights[];
+            };
+
+            layout(push_constant) uniform LightingPC {
+                vec4 camera_pos;
+                vec4 ambient;
+                vec4 background;
+                uint light_count;
+                uint debug_view;
+            };
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                vec4 albedo = subpassLoad(u_albedo);
+                vec3 normal = subpassLoad(u_normal).xyz;
+                vec4 position = subpassLoad(u_position);
+                uint material = subpassLoad(u_material).r;
+
+                if (albedo.a == 0.0) {
+                    f_color = background;
+                    return;
+                }
+
+                vec3 to_camera = camera_pos.xyz - position.xyz;
+                if (dot(normal, to_camera) < 0.0) {
+                    normal = -normal;
+                }
+
+                switch (debug_view) {
+                    case 1: f_color = vec4(albedo.rgb, 1.0); return;
+                    case 2: f_color = vec4(normal * 0.5 + 0.5, 1.0); return;
+                    case 3: f_color = vec4(fract(position.xyz / 10.0), 1.0); return;
+                    case 4: f_color = vec4(vec3(1.0 - exp(-position.w * 0.05)), 1.0); return;
+                    case 5: {
+                        // hash the id into a stable color
+                        uint h = material * 2654435761u;
+                        f_color = vec4(vec3(h & 255u, (h >> 8) & 255u, (h >> 16) & 255u) / 255.0, 1.0);
+                        return;
+                    }
+                }
+
+                vec3 light = ambient.rgb;
+
+                for (uint i = 0; i < light_count; i++) {
+                    vec3 to_light = lights[i].position_radius.xyz - position.xyz;
+                    float dist = length(to_light);
+                    float radius = lights[i].position_radius.w;
+                    if (dist >= radius) {
+                        continue;
+                    }
+
+                    float falloff = 1.0 - dist / radius;
+                    float diffuse = max(dot(normal, to_light / dist), 0.0);
+
+                    light += lights[i].color_intensity.rgb * lights[i].color_intensity.w * diffuse * falloff * falloff;
+                }
+
+                f_color = vec4(albedo.rgb * light, 1.0);
+            }
+        ",
+    }
+}
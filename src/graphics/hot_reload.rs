@@ -0,0 +1,401 @@
+use std::{any::Any, cell::RefCell, fmt, fs, io, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant, SystemTime}};
+
+use imgui::Ui;
+use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, SourceLanguage};
+use vulkano::{pipeline::Pipeline, shader::{ShaderModule, ShaderModuleCreateInfo}, Validated, VulkanError};
+
+use super::{reflect::ShaderReflection, vk::Vk};
+
+pub use shaderc::ShaderKind;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, io::Error),
+    Compile(String),
+    Module(Validated<VulkanError>),
+    /// The pipeline build function panicked, e.g. the new shader interface does not match
+    Pipeline(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ShaderError::Compile(e) => write!(f, "{e}"),
+            ShaderError::Module(e) => write!(f, "failed to create shader module: {e}"),
+            ShaderError::Pipeline(e) => write!(f, "failed to build pipeline: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Guesses the stage from the usual glslang extensions (`.vert`, `.frag`, `.comp`, ...),
+/// also accepting them before a `.glsl`/`.hlsl` suffix (`sky.frag.hlsl`)
+pub fn shader_kind_from_path(path: impl AsRef<Path>) -> Option<ShaderKind> {
+    let name = path.as_ref().file_name()?.to_str()?;
+
+    name.split('.').rev().find_map(|ext| match ext {
+        "vert" => Some(ShaderKind::Vertex),
+        "frag" => Some(ShaderKind::Fragment),
+        "comp" => Some(ShaderKind::Compute),
+        "geom" => Some(ShaderKind::Geometry),
+        "tesc" => Some(ShaderKind::TessControl),
+        "tese" => Some(ShaderKind::TessEvaluation),
+        _ => None,
+    })
+}
+
+/// GLSL includes of the crate, found by `compile_source` when a relative `#include` is not on disk
+const INCLUDES: &[(&str, &str)] = &[
+    ("colormap.glsl", include_str!("shaders/colormap.glsl")),
+    ("instance.glsl", include_str!("shaders/instance.glsl")),
+    ("lighting.glsl", include_str!("shaders/lighting.glsl")),
+    ("lit.frag", include_str!("shaders/lit.frag")),
+    ("model.glsl", include_str!("shaders/model.glsl")),
+    ("shadow.glsl", include_str!("shaders/shadow.glsl")),
+    ("terrain.glsl", include_str!("shaders/terrain.glsl")),
+];
+
+/// Output of `compile_source`
+pub struct CompiledSpirv {
+    pub words: Vec<u32>,
+    /// Included files read from disk, the crate's own includes are not listed
+    pub includes: Vec<PathBuf>,
+    pub warnings: String,
+}
+
+/// Output of `compile_file`: the module and every file it was built from (the file itself first)
+pub struct CompiledShader {
+    pub module: Arc<ShaderModule>,
+    pub sources: Vec<PathBuf>,
+    /// Kept for reflection, see `reflect::ShaderReflection`
+    pub spirv: Vec<u32>,
+    pub warnings: String,
+}
+
+/// Compiles GLSL (or HLSL, when `name` ends with `.hlsl`) to SPIR-V at runtime.
+/// `#include "..."` is resolved relative to `name`, then among the crate's includes (`model.glsl`, `instance.glsl`, ...).
+pub fn compile_source(source: &str, kind: ShaderKind, name: &str) -> Result<CompiledSpirv, ShaderError> {
+    let includes = RefCell::new(Vec::new());
+
+    let compiler = Compiler::new().expect("failed to initialize shaderc");
+    let mut options = CompileOptions::new().unwrap();
+
+    if name.ends_with(".hlsl") {
+        options.set_source_language(SourceLanguage::HLSL);
+    }
+
+    options.set_include_callback(|requested, ty, requesting, _depth| {
+        let base = match ty {
+            IncludeType::Relative => Path::new(requesting).parent().unwrap_or(Path::new(".")),
+            IncludeType::Standard => Path::new("."),
+        };
+        let include = base.join(requested);
+
+        match fs::read_to_string(&include) {
+            Ok(content) => {
+                includes.borrow_mut().push(include.clone());
+
+                Ok(ResolvedInclude {
+                    resolved_name: include.to_string_lossy().into_owned(),
+                    content,
+                })
+            }
+            Err(e) => INCLUDES
+                .iter()
+                .find(|(file, _)| *file == requested)
+                .map(|(file, content)| ResolvedInclude {
+                    resolved_name: file.to_string(),
+                    content: content.to_string(),
+                })
+                .ok_or_else(|| format!("{}: {e}", include.display())),
+        }
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(source, kind, name, "main", Some(&options))
+        .map_err(|e| ShaderError::Compile(e.to_string()))?;
+    drop(options);
+
+    Ok(CompiledSpirv {
+        words: artifact.as_binary().to_vec(),
+        includes: includes.into_inner(),
+        warnings: artifact.get_warning_messages(),
+    })
+}
+
+/// Compiles a shader file to a module at runtime, see `compile_source`
+pub fn compile_file(vk: Arc<Vk>, path: impl AsRef<Path>, kind: ShaderKind) -> Result<CompiledShader, ShaderError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ShaderError::Io(path.to_path_buf(), e))?;
+
+    let compiled = compile_source(&source, kind, &path.to_string_lossy())?;
+
+    /* the words come straight from shaderc */
+    let module = unsafe {
+        ShaderModule::new(vk.device.clone(), ShaderModuleCreateInfo::new(&compiled.words))
+    }
+    .map_err(ShaderError::Module)?;
+
+    let mut sources = vec![path.to_path_buf()];
+    sources.extend(compiled.includes);
+
+    Ok(CompiledShader {
+        module,
+        sources,
+        spirv: compiled.words,
+        warnings: compiled.warnings,
+    })
+}
+
+struct WatchedShader {
+    path: PathBuf,
+    kind: ShaderKind,
+    /// The file and its includes, with the modification time seen at the last compile
+    sources: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl WatchedShader {
+    fn changed(&self) -> bool {
+        self.sources.iter().any(|(path, modified)| modified_time(path) != *modified)
+    }
+
+    fn compile(&mut self, vk: Arc<Vk>) -> Result<CompiledShader, ShaderError> {
+        /* remember the times before compiling, so a failed compile is not retried until the files change again */
+        self.sources = std::mem::take(&mut self.sources)
+            .into_iter()
+            .map(|(path, _)| {
+                let modified = modified_time(&path);
+                (path, modified)
+            })
+            .collect();
+
+        let compiled = compile_file(vk, &self.path, self.kind)?;
+
+        self.sources = compiled.sources
+            .iter()
+            .map(|path| (path.clone(), modified_time(path)))
+            .collect();
+
+        Ok(compiled)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+type BuildFn<P> = Box<dyn Fn(Arc<Vk>, &[Arc<ShaderModule>]) -> Arc<P>>;
+
+/// Also registers the reflection in `Vk::reflections`. Prints and returns the warnings of every shader
+fn compile_and_build<P: Pipeline>(
+    vk: Arc<Vk>,
+    shaders: &mut [WatchedShader],
+    build: &BuildFn<P>,
+) -> Result<(Arc<P>, ShaderReflection, String), ShaderError> {
+    let compiled = shaders
+        .iter_mut()
+        .map(|shader| shader.compile(vk.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let warnings = compiled
+        .iter()
+        .map(|shader| shader.warnings.trim_end())
+        .filter(|warnings| !warnings.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !warnings.is_empty() {
+        println!("{warnings}");
+    }
+
+    let reflection = ShaderReflection::new(compiled.iter().map(|shader| shader.spirv.as_slice()))
+        .map_err(|e| ShaderError::Compile(e.to_string()))?;
+
+    let modules = compiled
+        .into_iter()
+        .map(|shader| shader.module)
+        .collect::<Vec<_>>();
+
+    let pipeline = panic::catch_unwind(AssertUnwindSafe(|| build(vk.clone(), &modules)))
+        .map_err(|e| ShaderError::Pipeline(panic_message(e)))?;
+    vk.reflections.insert(pipeline.layout(), reflection.clone());
+
+    Ok((pipeline, reflection, warnings))
+}
+
+/// A pipeline rebuilt from its shader files whenever one of them (or anything they include) changes.
+///
+/// When a reload fails the last good pipeline is kept, and the error is printed and kept in `error()`
+/// until the next successful reload. Compiler warnings are printed and kept in `warnings()`.
+///
+/// ```ignore
+/// let mut hot = HotPipeline::new(vk.clone(), ["shaders/mesh.vert", "shaders/mesh.frag"], move |vk, modules| {
+///     GraphicsPipelineBuilder::new(modules[0].clone(), modules[1].clone())
+///         .vertex::<PosVertex>()
+///         .build(vk, rp.clone())
+/// })?;
+///
+/// // every frame
+/// hot.poll(vk.clone());
+/// hot.show_error(ui);
+/// builder.bind_pipeline_graphics(hot.pipeline());
+/// ```
+pub struct HotPipeline<P> {
+    shaders: Vec<WatchedShader>,
+    build: BuildFn<P>,
+    pipeline: Arc<P>,
+    error: Option<String>,
+    warnings: String,
+    reflection: ShaderReflection,
+
+    /// Minimum time between two checks of the files
+    pub interval: Duration,
+    last_poll: Instant,
+}
+
+impl<P: Pipeline> HotPipeline<P> {
+    /// Stages are guessed from the file names, see `shader_kind_from_path`.
+    /// `build` gets the modules in the same order as `paths`.
+    pub fn new(
+        vk: Arc<Vk>,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        build: impl Fn(Arc<Vk>, &[Arc<ShaderModule>]) -> Arc<P> + 'static,
+    ) -> Result<Self, ShaderError> {
+        let shaders = paths
+            .into_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let kind = shader_kind_from_path(path).unwrap_or(ShaderKind::InferFromSource);
+                (path.to_path_buf(), kind)
+            })
+            .collect::<Vec<_>>();
+
+        Self::with_kinds(vk, shaders, build)
+    }
+
+    pub fn with_kinds(
+        vk: Arc<Vk>,
+        shaders: impl IntoIterator<Item = (PathBuf, ShaderKind)>,
+        build: impl Fn(Arc<Vk>, &[Arc<ShaderModule>]) -> Arc<P> + 'static,
+    ) -> Result<Self, ShaderError> {
+        let mut shaders = shaders
+            .into_iter()
+            .map(|(path, kind)| WatchedShader {
+                sources: vec![(path.clone(), None)],
+                path,
+                kind,
+            })
+            .collect::<Vec<_>>();
+
+        let build: BuildFn<P> = Box::new(build);
+        let (pipeline, reflection, warnings) = compile_and_build(vk, &mut shaders, &build)?;
+
+        Ok(Self {
+            shaders,
+            build,
+            pipeline,
+            error: None,
+            warnings,
+            reflection,
+
+            interval: Duration::from_millis(250),
+            last_poll: Instant::now(),
+        })
+    }
+
+    /// Recompiles every shader and rebuilds the pipeline if any file changed.
+    /// Returns true when the pipeline was replaced.
+    pub fn poll(&mut self, vk: Arc<Vk>) -> bool {
+        if self.last_poll.elapsed() < self.interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        if !self.shaders.iter().any(WatchedShader::changed) {
+            return false;
+        }
+
+        match self.reload(vk) {
+            Ok(()) => {
+                self.error = None;
+                true
+            }
+            Err(e) => {
+                println!("shader reload failed, keeping the previous pipeline:\n{e}");
+                self.error = Some(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// Recompiles and rebuilds unconditionally
+    pub fn reload(&mut self, vk: Arc<Vk>) -> Result<(), ShaderError> {
+        let (pipeline, reflection, warnings) = compile_and_build(vk, &mut self.shaders, &self.build)?;
+        self.pipeline = pipeline;
+        self.reflection = reflection;
+        self.warnings = warnings;
+
+        Ok(())
+    }
+
+    /// The last pipeline that built successfully
+    pub fn pipeline(&self) -> Arc<P> {
+        self.pipeline.clone()
+    }
+
+    /// Descriptors of the shaders behind `pipeline()`, see `reflect::DescriptorWriter`
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+
+    /// Error of the last reload, if it failed
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Compiler warnings of the shaders behind `pipeline()`, empty when there are none
+    pub fn warnings(&self) -> &str {
+        &self.warnings
+    }
+
+    /// Shows the error of the last reload and the warnings in an ImGui window,
+    /// does nothing while there are none
+    pub fn show_error(&self, ui: &Ui) {
+        if self.error.is_none() && self.warnings.is_empty() {
+            return;
+        }
+
+        let title = self.shaders
+            .iter()
+            .map(|shader| shader.path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        ui.window(format!("shader error: {title}"))
+            .size([520.0, 240.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                if let Some(error) = &self.error {
+                    ui.text_colored([1.0, 0.35, 0.35, 1.0], "reload failed, using the previous pipeline");
+                    ui.separator();
+                    ui.text_wrapped(error);
+                }
+
+                if !self.warnings.is_empty() {
+                    ui.text_colored([1.0, 0.8, 0.35, 1.0], "warnings");
+                    ui.separator();
+                    ui.text_wrapped(&self.warnings);
+                }
+            });
+    }
+}
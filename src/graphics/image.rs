@@ -1,9 +1,74 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
-use vulkano::{command_buffer::{CopyBufferToImageInfo, CopyImageToBufferInfo}, format::Format, image::{Image, ImageCreateInfo, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}};
+use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage}, command_buffer::{BlitImageInfo, BufferImageCopy, CopyBufferToImageInfo, CopyImageToBufferInfo, ImageBlit}, format::{Format, FormatFeatures, NumericFormat}, image::{sampler::Filter, view::{ImageView, ImageViewCreateInfo, ImageViewType}, Image, ImageAspect, ImageAspects, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageType, ImageUsage, SampleCount}, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}};
 
 use super::{buffer::VkIterBuffer, command::{submit_cmd_buf, BuilderType, VkBuilder}, vk::{MemAllocators, Vk}};
 
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    /// Cubemap faces are not square or do not share the same size and format
+    FaceMismatch,
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    /// Store 8 bit images as R8G8B8A8_SRGB (color data) instead of R8G8B8A8_UNORM (normals, masks...)
+    pub srgb: bool,
+    /// Allocate the full mip chain and generate it after the upload
+    pub mipmaps: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            srgb: true,
+            mipmaps: false,
+        }
+    }
+}
+
+/// One mip level of one array layer of an image
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImageSubresource {
+    pub mip_level: u32,
+    pub array_layer: u32,
+    /// `None` picks color, or depth for depth/stencil formats
+    pub aspect: Option<ImageAspect>,
+}
+
+impl ImageSubresource {
+    pub fn new(mip_level: u32, array_layer: u32) -> Self {
+        Self {
+            mip_level,
+            array_layer,
+            aspect: None,
+        }
+    }
+}
+
+/// Number of levels in a full mip chain for `extent`
+pub fn mip_levels_for(extent: [u32; 3]) -> u32 {
+    32 - extent[0].max(extent[1]).max(extent[2]).max(1).leading_zeros()
+}
+
+pub fn mip_extent(extent: [u32; 3], level: u32) -> [u32; 3] {
+    extent.map(|e| (e >> level).max(1))
+}
+
 pub struct VkImage {
     pub content: Arc<Image>,
 }
@@ -29,6 +94,222 @@ impl VkImage {
         }
     }
 
+    /// Device local sampled image, filled through `copy_buffer_to_image` or a staging upload
+    pub fn sampler_device(allocators: Arc<MemAllocators>, format: Format, extent: [u32; 3], mip_levels: u32) -> Self {
+        Self {
+            content: Image::new(
+                allocators.memory.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent,
+                    mip_levels,
+                    usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Decodes a PNG, JPEG or Radiance HDR file into a device local image
+    pub fn from_file(
+        vk: Arc<Vk>, 
+        path: impl AsRef<Path>, 
+        options: TextureOptions,
+    ) -> Result<(Self, Arc<ImageView>), TextureError> {
+        let bytes = std::fs::read(path)?;
+
+        Self::from_memory(vk, &bytes, options)
+    }
+
+    /// Same as `from_file`, with the encoded file already in memory.
+    /// 
+    /// The format is picked from the decoded data:
+    /// HDR -> R32G32B32A32_SFLOAT, everything else -> R8G8B8A8_SRGB or R8G8B8A8_UNORM
+    pub fn from_memory(
+        vk: Arc<Vk>, 
+        bytes: &[u8], 
+        options: TextureOptions,
+    ) -> Result<(Self, Arc<ImageView>), TextureError> {
+        let (format, extent, pixels) = decode(bytes, options)?;
+        let mip_levels = if options.mipmaps { mip_levels_for(extent) } else { 1 };
+
+        let image = Self::sampler_device(vk.allocators.clone(), format, extent, mip_levels);
+        image.upload_pixels(vk.clone(), ImageSubresource::default(), &pixels);
+
+        if mip_levels > 1 {
+            image.generate_mipmaps(vk.clone());
+        }
+
+        let view = vk.image_views.get_default(&image.content);
+
+        Ok((image, view))
+    }
+
+    /// Device local cube compatible image with 6 array layers, one per face (+X, -X, +Y, -Y, +Z, -Z)
+    pub fn cubemap(
+        allocators: Arc<MemAllocators>, 
+        format: Format, 
+        size: u32, 
+        mip_levels: u32, 
+        usage: ImageUsage,
+    ) -> Self {
+        Self {
+            content: Image::new(
+                allocators.memory.clone(),
+                ImageCreateInfo {
+                    flags: ImageCreateFlags::CUBE_COMPATIBLE,
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [size, size, 1],
+                    array_layers: 6,
+                    mip_levels,
+                    usage: usage | ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Builds a cubemap from six square face images, in the order +X, -X, +Y, -Y, +Z, -Z.
+    /// Returns the image with a `Cube` view
+    pub fn from_cube_faces(
+        vk: Arc<Vk>, 
+        faces: [impl AsRef<Path>; 6], 
+        options: TextureOptions,
+    ) -> Result<(Self, Arc<ImageView>), TextureError> {
+        let mut decoded = Vec::with_capacity(6);
+        for face in faces {
+            decoded.push(decode(&std::fs::read(face)?, options)?);
+        }
+
+        let (format, extent, _) = decoded[0];
+        if extent[0] != extent[1] || decoded.iter().any(|(f, e, _)| *f != format || *e != extent) {
+            return Err(TextureError::FaceMismatch);
+        }
+
+        let mip_levels = if options.mipmaps { mip_levels_for(extent) } else { 1 };
+        let image = Self::cubemap(vk.allocators.clone(), format, extent[0], mip_levels, ImageUsage::empty());
+
+        for (layer, (_, _, pixels)) in decoded.iter().enumerate() {
+            image.upload_pixels(vk.clone(), ImageSubresource::new(0, layer as u32), pixels);
+        }
+
+        if mip_levels > 1 {
+            image.generate_mipmaps(vk.clone());
+        }
+
+        let view = image.cube_view(vk);
+
+        Ok((image, view))
+    }
+
+    /// `Cube` view over all 6 layers of a cube compatible image
+    pub fn cube_view(&self, vk: Arc<Vk>) -> Arc<ImageView> {
+        vk.image_views.get(
+            &self.content, 
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Cube,
+                ..ImageViewCreateInfo::from_image(&self.content)
+            },
+        )
+    }
+
+    fn upload_pixels(&self, vk: Arc<Vk>, sub: ImageSubresource, pixels: &Pixels) {
+        match pixels {
+            Pixels::U8(data) => self.upload(vk, sub, data),
+            Pixels::F32(data) => self.upload(vk, sub, data),
+        }
+    }
+
+    /// Fills mip levels 1.. from level 0.
+    /// 
+    /// Uses successive linear `blit_image`s when the format supports them,
+    /// otherwise reads level 0 back and builds the chain with a box filter on the CPU
+    pub fn generate_mipmaps(&self, vk: Arc<Vk>) {
+        let image = &self.content;
+        if image.mip_levels() < 2 {
+            return;
+        }
+
+        let features = vk.physical_device
+            .format_properties(image.format())
+            .unwrap()
+            .optimal_tiling_features;
+
+        let blit_features = FormatFeatures::BLIT_SRC | FormatFeatures::BLIT_DST | FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR;
+        if features.contains(blit_features) {
+            self.blit_mipmaps(vk);
+        } else {
+            self.cpu_mipmaps(vk);
+        }
+    }
+
+    fn blit_mipmaps(&self, vk: Arc<Vk>) {
+        let image = &self.content;
+        let aspects = image.format().aspects();
+        let mut builder = VkBuilder::new_once(vk.clone());
+
+        for level in 1..image.mip_levels() {
+            let src_extent = mip_extent(image.extent(), level - 1);
+            let dst_extent = mip_extent(image.extent(), level);
+
+            builder.0
+                .blit_image(BlitImageInfo {
+                    regions: [ImageBlit {
+                        src_subresource: ImageSubresourceLayers {
+                            aspects,
+                            mip_level: level - 1,
+                            array_layers: 0..image.array_layers(),
+                        },
+                        src_offsets: [[0; 3], src_extent],
+                        dst_subresource: ImageSubresourceLayers {
+                            aspects,
+                            mip_level: level,
+                            array_layers: 0..image.array_layers(),
+                        },
+                        dst_offsets: [[0; 3], dst_extent],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    filter: Filter::Linear,
+                    ..BlitImageInfo::images(image.clone(), image.clone())
+                })
+                .unwrap();
+        }
+
+        submit_cmd_buf(vk, builder.command_buffer())
+            .wait(None)
+            .unwrap();
+    }
+
+    fn cpu_mipmaps(&self, vk: Arc<Vk>) {
+        let image = &self.content;
+        let format = image.format();
+
+        for layer in 0..image.array_layers() {
+            let mut data = self.download::<u8>(vk.clone(), ImageSubresource::new(0, layer));
+
+            for level in 1..image.mip_levels() {
+                let src_extent = mip_extent(image.extent(), level - 1);
+                let dst_extent = mip_extent(image.extent(), level);
+
+                data = box_filter(format, &data, src_extent, dst_extent);
+                self.upload(vk.clone(), ImageSubresource::new(level, layer), &data);
+            }
+        }
+    }
+
     pub fn depth(allocators: Arc<MemAllocators>, format: Format, extent: [u32; 3]) -> Self {
         Self {
             content: Image::new(
@@ -45,88 +326,301 @@ impl VkImage {
         }
     }
 
-    /// Assuming data is R8G8B8A8
+    /// Color attachment that later passes can sample
+    pub fn render_target(allocators: Arc<MemAllocators>, format: Format, extent: [u32; 3]) -> Self {
+        Self {
+            content: Image::new(
+                allocators.memory.clone(),
+                ImageCreateInfo {
+                    format,
+                    extent,
+                    usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Multisampled render target that is only resolved, never stored (color or depth, from `usage`)
+    pub fn multisampled(allocators: Arc<MemAllocators>, format: Format, extent: [u32; 3], samples: SampleCount, usage: ImageUsage) -> Self {
+        Self {
+            content: Image::new(
+                allocators.memory.clone(),
+                ImageCreateInfo {
+                    format,
+                    extent,
+                    samples,
+                    usage: usage | ImageUsage::TRANSIENT_ATTACHMENT,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Copies the first mip level / array layer from `data`, which must hold at least 
+    /// `subresource_size(ImageSubresource::default())` bytes
     pub fn copy_buffer_to_image(
         &self, 
         vk: Arc<Vk>,
         data: &[u8],
     ) {
-        let image = &self.content;
-        let extent = self.content.extent();
+        let sub = ImageSubresource::default();
+        let size = self.subresource_size(sub) as usize;
 
-        let mut builder = VkBuilder::new_multiple(vk.clone());
+        self.upload(vk, sub, &data[..size]);
+    }
 
-        let buffer = VkIterBuffer::transfer_src(
-            vk.allocators.clone(), 
-            (0..(extent[0]*extent[1]*4)).map(|i| {
-                data[i as usize]
-            }),
-        );
+    /// Reads back the first mip level / array layer as raw bytes
+    pub fn copy_image_to_buffer(
+        &self, 
+        vk: Arc<Vk>,
+    ) -> VkIterBuffer<u8> {
+        let mut builder = VkBuilder::new_once(vk.clone());
 
-        builder.0
-            .copy_buffer_to_image(
-                CopyBufferToImageInfo::buffer_image(buffer.content.clone(), image.clone())
-            )
-            .unwrap();
+        let buffer = self.submit_download(vk.clone(), &mut builder.0, ImageSubresource::default());
 
         let cmd_buf = builder.command_buffer();
         
         let fut = submit_cmd_buf(vk.clone(), cmd_buf);
         fut.wait(None).unwrap();
+
+        buffer
     }
 
-    pub fn copy_image_to_buffer(
+    pub fn submit_copy_image_to_buffer(
         &self, 
         vk: Arc<Vk>,
+        builder: &mut BuilderType,
     ) -> VkIterBuffer<u8> {
-        let image = &self.content;
-        let extent = self.content.extent();
+        self.submit_download(vk, builder, ImageSubresource::default())
+    }
 
-        let mut builder = VkBuilder::new_multiple(vk.clone());
+    fn resolve_aspect(&self, sub: ImageSubresource) -> ImageAspect {
+        sub.aspect.unwrap_or_else(|| default_aspect(self.content.format()))
+    }
 
-        let buffer = VkIterBuffer::transfer_dst(
-            vk.allocators.clone(), 
-            (0..(extent[0]*extent[1]*4)).map(|_| {
-                0u8
-            }),
+    /// Size in bytes of one mip level of one array layer, as laid out in a buffer
+    pub fn subresource_size(&self, sub: ImageSubresource) -> u64 {
+        let format = self.content.format();
+        let block = format.block_extent();
+        let [w, h, d] = mip_extent(self.content.extent(), sub.mip_level);
+
+        let blocks = w.div_ceil(block[0]) as u64 * h.div_ceil(block[1]) as u64 * d.div_ceil(block[2]) as u64;
+
+        blocks * texel_block_size(format, self.resolve_aspect(sub))
+    }
+
+    fn region(&self, sub: ImageSubresource) -> BufferImageCopy {
+        BufferImageCopy {
+            image_subresource: ImageSubresourceLayers {
+                aspects: self.resolve_aspect(sub).into(),
+                mip_level: sub.mip_level,
+                array_layers: sub.array_layer..sub.array_layer + 1,
+            },
+            image_extent: mip_extent(self.content.extent(), sub.mip_level),
+            ..Default::default()
+        }
+    }
+
+    /// Uploads `data` into one subresource and waits for the copy.
+    /// `data` must match the subresource size exactly, e.g. `&[f32]` for an R32_SFLOAT image
+    pub fn upload<T: BufferContents + Copy>(&self, vk: Arc<Vk>, sub: ImageSubresource, data: &[T]) {
+        let size = self.subresource_size(sub);
+        assert_eq!(
+            std::mem::size_of_val(data) as u64, size,
+            "upload data does not match {:?} mip {} layer {}", self.content.format(), sub.mip_level, sub.array_layer,
         );
 
+        let buffer = VkIterBuffer::transfer_src(vk.allocators.clone(), data.iter().copied());
+
+        let mut builder = VkBuilder::new_once(vk.clone());
         builder.0
-            .copy_image_to_buffer(
-                CopyImageToBufferInfo::image_buffer(image.clone(), buffer.content.clone())
-            )
+            .copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: [self.region(sub)].into(),
+                ..CopyBufferToImageInfo::buffer_image(buffer.content.clone(), self.content.clone())
+            })
             .unwrap();
 
-        let cmd_buf = builder.command_buffer();
-        
-        let fut = submit_cmd_buf(vk.clone(), cmd_buf);
-        fut.wait(None).unwrap();
+        submit_cmd_buf(vk, builder.command_buffer())
+            .wait(None)
+            .unwrap();
+    }
 
-        buffer
+    /// Reads one subresource back as typed texels, e.g. `download::<u16>` for D16_UNORM depth
+    pub fn download<T: BufferContents + Copy>(&self, vk: Arc<Vk>, sub: ImageSubresource) -> Vec<T> {
+        let mut builder = VkBuilder::new_once(vk.clone());
+
+        let buffer = self.submit_download::<T>(vk.clone(), &mut builder.0, sub);
+
+        submit_cmd_buf(vk, builder.command_buffer())
+            .wait(None)
+            .unwrap();
+
+        let data = buffer.content.read().unwrap();
+        data.to_vec()
     }
 
-    pub fn submit_copy_image_to_buffer(
+    /// Records the copy of one subresource into a host visible buffer,
+    /// which can be read once the command buffer has finished
+    pub fn submit_download<T: BufferContents>(
         &self, 
-        vk: Arc<Vk>,
-        builder: &mut BuilderType,
-    ) -> VkIterBuffer<u8> {
-        let image = &self.content;
-        let extent = self.content.extent();
-
-        let buffer = VkIterBuffer::transfer_dst(
-            vk.allocators.clone(), 
-            (0..(extent[0]*extent[1]*4)).map(|_| {
-                0u8
-            }),
+        vk: Arc<Vk>, 
+        builder: &mut BuilderType, 
+        sub: ImageSubresource,
+    ) -> VkIterBuffer<T> {
+        let size = self.subresource_size(sub);
+        let texel = std::mem::size_of::<T>() as u64;
+        assert!(
+            size.is_multiple_of(texel),
+            "{:?} mip {} is {} bytes, not a multiple of the {} byte element", self.content.format(), sub.mip_level, size, texel,
         );
 
+        let buffer = Buffer::new_slice::<T>(
+            vk.allocators.memory.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            size / texel,
+        )
+        .expect("failed to create buffer");
+
         builder
-            .copy_image_to_buffer(
-                CopyImageToBufferInfo::image_buffer(image.clone(), buffer.content.clone())
-            )
+            .copy_image_to_buffer(CopyImageToBufferInfo {
+                regions: [self.region(sub)].into(),
+                ..CopyImageToBufferInfo::image_buffer(self.content.clone(), buffer.clone())
+            })
             .unwrap();
 
-        buffer
+        VkIterBuffer {
+            content: buffer,
+        }
     }
-    
-}
\ No newline at end of file
+}
+
+/// The aspect copied when none is asked for: color, or depth for depth/stencil formats
+fn default_aspect(format: Format) -> ImageAspect {
+    let aspects = format.aspects();
+    if aspects.intersects(ImageAspects::DEPTH) {
+        ImageAspect::Depth
+    } else if aspects.intersects(ImageAspects::STENCIL) {
+        ImageAspect::Stencil
+    } else {
+        ImageAspect::Color
+    }
+}
+
+/// Bytes per texel block of `aspect` when copied to or from a buffer.
+/// Depth and stencil aspects of combined formats are tightly packed on their own
+pub fn texel_block_size(format: Format, aspect: ImageAspect) -> u64 {
+    match aspect {
+        ImageAspect::Stencil => 1,
+        ImageAspect::Depth => match format {
+            Format::D16_UNORM | Format::D16_UNORM_S8_UINT => 2,
+            _ => 4,
+        },
+        _ => format.block_size(),
+    }
+}
+
+enum Pixels {
+    U8(Vec<u8>),
+    F32(Vec<f32>),
+}
+
+/// Decodes an encoded image into RGBA texels.
+/// HDR -> R32G32B32A32_SFLOAT, everything else -> R8G8B8A8_SRGB or R8G8B8A8_UNORM
+fn decode(bytes: &[u8], options: TextureOptions) -> Result<(Format, [u32; 3], Pixels), TextureError> {
+    let decoded = image::load_from_memory(bytes)?;
+    let extent = [decoded.width(), decoded.height(), 1];
+
+    Ok(match decoded {
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => {
+            (Format::R32G32B32A32_SFLOAT, extent, Pixels::F32(decoded.into_rgba32f().into_raw()))
+        }
+        _ => {
+            let format = if options.srgb { Format::R8G8B8A8_SRGB } else { Format::R8G8B8A8_UNORM };
+            (format, extent, Pixels::U8(decoded.into_rgba8().into_raw()))
+        }
+    })
+}
+
+/// 2x2 box filter of one 2D mip level into the next one.
+/// 
+/// 8 bit and 32 bit float channels are averaged (sRGB in linear space),
+/// any other format falls back to picking the top left texel of each block
+fn box_filter(format: Format, src: &[u8], src_extent: [u32; 3], dst_extent: [u32; 3]) -> Vec<u8> {
+    let texel_size = format.block_size() as usize;
+    let channels = format.components().iter().filter(|&&bits| bits > 0).count();
+    let bits = format.components()[0];
+    let numeric = format.numeric_format_color();
+
+    let (sw, sh) = (src_extent[0] as usize, src_extent[1] as usize);
+    let (dw, dh) = (dst_extent[0] as usize, dst_extent[1] as usize);
+    let mut dst = vec![0u8; dw * dh * texel_size];
+
+    for y in 0..dh {
+        for x in 0..dw {
+            let taps = [
+                (2 * x, 2 * y), 
+                ((2 * x + 1).min(sw - 1), 2 * y), 
+                (2 * x, (2 * y + 1).min(sh - 1)), 
+                ((2 * x + 1).min(sw - 1), (2 * y + 1).min(sh - 1)),
+            ]
+            .map(|(tx, ty)| (ty.min(sh - 1) * sw + tx.min(sw - 1)) * texel_size);
+
+            let out = &mut dst[(y * dw + x) * texel_size..][..texel_size];
+
+            match (bits, numeric) {
+                (8, Some(NumericFormat::SRGB)) if texel_size == channels => {
+                    for c in 0..channels {
+                        /* alpha is always linear */
+                        let linear = c == 3;
+                        let sum: f32 = taps.iter()
+                            .map(|&t| {
+                                let v = src[t + c] as f32 / 255.0;
+                                if linear { v } else { srgb_to_linear(v) }
+                            })
+                            .sum();
+                        let v = sum / 4.0;
+                        out[c] = ((if linear { v } else { linear_to_srgb(v) }) * 255.0 + 0.5) as u8;
+                    }
+                }
+                (8, Some(NumericFormat::UNORM | NumericFormat::UINT)) if texel_size == channels => {
+                    for c in 0..channels {
+                        let sum: u32 = taps.iter().map(|&t| src[t + c] as u32).sum();
+                        out[c] = ((sum + 2) / 4) as u8;
+                    }
+                }
+                (32, Some(NumericFormat::SFLOAT)) if texel_size == channels * 4 => {
+                    for c in 0..channels {
+                        let sum: f32 = taps.iter()
+                            .map(|&t| f32::from_ne_bytes(src[t + c * 4..][..4].try_into().unwrap()))
+                            .sum();
+                        out[c * 4..][..4].copy_from_slice(&(sum / 4.0).to_ne_bytes());
+                    }
+                }
+                _ => out.copy_from_slice(&src[taps[0]..][..texel_size]),
+            }
+        }
+    }
+
+    dst
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+use vulkano::{buffer::BufferContents, descriptor_set::WriteDescriptorSet, pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint}, shader::ShaderModule};
+
+use super::{buffer::{VkBuffer, VkIterBuffer}, command::BuilderType, pipeline::GraphicsPipelineBuilder, utils::descriptor_set, vertex::{ModelInstanceData, NormalVertex, PosInstanceData, PosVertex}, vk::Vk};
+
+pub mod shaders;
+
+/// Set of the lights in the lit shaders: the camera is set 0, `Mesh` model and material set 1
+pub const LIGHT_SET: u32 = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    Directional {
+        /// Direction the light travels in
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        /// Distance where the light reaches 0
+        range: f32,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        /// Half angles of the cone in radians, full intensity inside `inner_angle`
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    pub fn data(&self) -> LightData {
+        match *self {
+            Light::Directional { direction, color, intensity } => LightData {
+                position_range: [0.0; 4],
+                direction_type: direction.normalize().extend(0.0).to_array(),
+                color_intensity: color.extend(intensity).to_array(),
+                cone: [0.0; 4],
+            },
+            Light::Point { position, color, intensity, range } => LightData {
+                position_range: position.extend(range).to_array(),
+                direction_type: [0.0, 0.0, 0.0, 1.0],
+                color_intensity: color.extend(intensity).to_array(),
+                cone: [0.0; 4],
+            },
+            Light::Spot { position, direction, color, intensity, range, inner_angle, outer_angle } => LightData {
+                position_range: position.extend(range).to_array(),
+                direction_type: direction.normalize().extend(2.0).to_array(),
+                color_intensity: color.extend(intensity).to_array(),
+                cone: [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+            },
+        }
+    }
+}
+
+/// `Light` in `shaders/lighting.glsl`
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct LightData {
+    pub position_range: [f32; 4],
+    pub direction_type: [f32; 4],
+    pub color_intensity: [f32; 4],
+    pub cone: [f32; 4],
+}
+
+/// `LightInfo` uniform of the lit shaders
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct LightInfo {
+    pub ambient: [f32; 4],
+    pub light_count: u32,
+}
+
+/// Every light of the scene, uploaded to a storage buffer at `LIGHT_SET` each time they are bound
+pub struct Lights {
+    pub lights: Vec<Light>,
+    /// Multiplied by the base color, in place of indirect lighting
+    pub ambient: Vec3,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Self {
+            lights: vec![],
+            ambient: Vec3::splat(0.03),
+        }
+    }
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    /// Lights at binding 0 and `LightInfo` at binding 1
+    pub fn writes(&self, vk: Arc<Vk>) -> [WriteDescriptorSet; 2] {
+        /* storage buffers can not be empty, the count keeps the padding light out */
+        let mut data = self.lights
+            .iter()
+            .map(Light::data)
+            .collect::<Vec<_>>();
+        if data.is_empty() {
+            data.push(Light::Directional { direction: Vec3::NEG_Y, color: Vec3::ZERO, intensity: 0.0 }.data());
+        }
+
+        let lights = VkIterBuffer::storage(vk.allocators.clone(), data.into_iter());
+        let info = VkBuffer::uniform(vk.allocators.clone(), LightInfo {
+            ambient: self.ambient.extend(1.0).to_array(),
+            light_count: self.lights.len() as u32,
+        });
+
+        [
+            WriteDescriptorSet::buffer(0, lights.content),
+            WriteDescriptorSet::buffer(1, info.content),
+        ]
+    }
+
+    /// Warning: this function assumes `pipeline` has already been bound
+    pub fn bind(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) {
+        let set = descriptor_set(vk.clone(), LIGHT_SET as usize, pipeline.clone(), self.writes(vk)).0;
+
+        builder
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), LIGHT_SET, set)
+            .unwrap();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ShadingModel {
+    BlinnPhong,
+    /// Metallic-roughness Cook-Torrance
+    #[default]
+    Pbr,
+}
+
+/// Builder for the lit shaders with the `Mesh` vertex buffers, ready to be drawn with `Mesh::build_commands`.
+///
+/// Set 0 is `Camera { mat4 view; mat4 proj; }` like the other mesh shaders,
+/// set 1 the model and material (see `Material::writes`), set 2 the lights (see `Lights::bind`).
+pub fn lit_pipeline(vk: Arc<Vk>, model: ShadingModel) -> GraphicsPipelineBuilder {
+    let vs = shaders::vs::load(vk.device.clone()).unwrap();
+    let (fs, fs_source) = lit_fs(vk, model);
+
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .instance::<PosInstanceData>()
+        .vertex::<NormalVertex>()
+        .reflect(shaders::vs::SOURCE, fs_source)
+}
+
+/// `lit_pipeline` for meshes with `Mesh::set_instance_transforms`, tinted by `instance_color`
+pub fn lit_instanced_pipeline(vk: Arc<Vk>, model: ShadingModel) -> GraphicsPipelineBuilder {
+    let vs = shaders::instanced_vs::load(vk.device.clone()).unwrap();
+    let (fs, fs_source) = lit_fs(vk, model);
+
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .instance::<ModelInstanceData>()
+        .vertex::<NormalVertex>()
+        .reflect(shaders::instanced_vs::SOURCE, fs_source)
+}
+
+/// Fragment shader of `model` with its source, for `GraphicsPipelineBuilder::reflect`
+pub fn lit_fs(vk: Arc<Vk>, model: ShadingModel) -> (Arc<ShaderModule>, &'static str) {
+    match model {
+        ShadingModel::BlinnPhong => (shaders::blinn_phong_fs::load(vk.device.clone()).unwrap(), shaders::blinn_phong_fs::SOURCE),
+        ShadingModel::Pbr => (shaders::pbr_fs::load(vk.device.clone()).unwrap(), shaders::pbr_fs::SOURCE),
+    }
+}
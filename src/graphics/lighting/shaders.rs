@@ -0,0 +1,41 @@
+/// `Mesh` inputs with the `NormalVertex` attributes
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/lit.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/lit.vert");
+}
+
+/// `vs` with `ModelInstanceData` in place of `PosInstanceData`, see `lit_instanced_pipeline`
+pub mod instanced_vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/lit_instanced.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/lit_instanced.vert");
+}
+
+pub mod blinn_phong_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/blinn_phong.frag",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/blinn_phong.frag");
+}
+
+pub mod pbr_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/pbr.frag",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/pbr.frag");
+}
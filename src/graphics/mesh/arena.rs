@@ -0,0 +1,455 @@
+use std::{ops::Range, sync::Arc};
+
+use glam::Mat4;
+use vulkano::{buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer}, command_buffer::CopyBufferInfo, descriptor_set::WriteDescriptorSet, memory::allocator::{AllocationCreateInfo, MemoryTypeFilter}, pipeline::{GraphicsPipeline, Pipeline}};
+
+use crate::graphics::{buffer::{VkBuffer, VkIterBuffer}, command::BuilderType, utils::descriptor_set, vertex::{PosInstanceData, PosVertex}, vk::Vk};
+
+use super::mesh::{Mesh, Model};
+
+/// First-fit free list over a range of elements.
+/// Freed ranges are merged with their neighbours so they can be reused by bigger meshes.
+struct RangeAllocator {
+    free: Vec<Range<u32>>,
+    capacity: u32,
+}
+
+impl RangeAllocator {
+    fn new(capacity: u32) -> Self {
+        let mut ranges = Self {
+            free: Vec::new(),
+            capacity: 0,
+        };
+        ranges.grow(capacity);
+
+        ranges
+    }
+
+    fn alloc(&mut self, len: u32) -> Option<Range<u32>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+
+        let i = self.free.iter().position(|r| r.end - r.start >= len)?;
+        let start = self.free[i].start;
+
+        self.free[i].start += len;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+
+        Some(start..start + len)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let i = self.free.partition_point(|r| r.start < range.start);
+        debug_assert!(
+            range.end <= self.capacity
+                && (i == 0 || self.free[i - 1].end <= range.start)
+                && (i == self.free.len() || range.end <= self.free[i].start),
+            "range {range:?} is out of bounds or was already freed"
+        );
+        self.free.insert(i, range);
+
+        /* merge with the next block, then with the previous one */
+        if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+            self.free[i].end = self.free[i + 1].end;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].end == self.free[i].start {
+            self.free[i - 1].end = self.free[i].end;
+            self.free.remove(i);
+        }
+    }
+
+    fn grow(&mut self, new_capacity: u32) {
+        let old = self.capacity;
+        self.capacity = new_capacity;
+        self.free(old..new_capacity);
+    }
+
+    fn used(&self) -> u32 {
+        self.capacity - self.free.iter().map(|r| r.end - r.start).sum::<u32>()
+    }
+}
+
+/// Element range of a buffer, one end of a queued copy
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Slot<B> {
+    buffer: B,
+    range: Range<u32>,
+}
+
+/// A `RangeAllocator` over one buffer. The copies filling it are queued rather than submitted,
+/// so staged writes and the move of the contents into a grown buffer run in the order they were made.
+struct ArenaBuffer<B> {
+    buffer: B,
+    ranges: RangeAllocator,
+    /// (source, destination), in order
+    copies: Vec<(Slot<B>, Slot<B>)>,
+}
+
+impl<B: Clone> ArenaBuffer<B> {
+    fn new(buffer: B, capacity: u32) -> Self {
+        Self {
+            buffer,
+            ranges: RangeAllocator::new(capacity),
+            copies: Vec::new(),
+        }
+    }
+
+    /// Without a free range big enough, the buffer is replaced by `create(new_capacity)`
+    /// and the copy of the old contents is queued before anything written afterwards
+    fn alloc(&mut self, len: u32, create: impl FnOnce(u32) -> B) -> Range<u32> {
+        if let Some(range) = self.ranges.alloc(len) {
+            return range;
+        }
+
+        let capacity = self.ranges.capacity;
+        let new_capacity = (capacity * 2).max(capacity + len);
+        let old = std::mem::replace(&mut self.buffer, create(new_capacity));
+
+        if capacity > 0 {
+            self.copies.push((
+                Slot { buffer: old, range: 0..capacity },
+                Slot { buffer: self.buffer.clone(), range: 0..capacity },
+            ));
+        }
+
+        self.ranges.grow(new_capacity);
+        self.ranges.alloc(len).unwrap()
+    }
+
+    /// Queues the copy of the start of `src` into `range`
+    fn write(&mut self, src: B, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.copies.push((
+            Slot { buffer: src, range: 0..range.end - range.start },
+            Slot { buffer: self.buffer.clone(), range },
+        ));
+    }
+
+    fn take_copies(&mut self) -> Vec<(Slot<B>, Slot<B>)> {
+        std::mem::take(&mut self.copies)
+    }
+}
+
+/// A mesh living inside a `MeshArena`.
+/// Indices are stored relative to the mesh, `vertex_offset` is added by the draw call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaMesh {
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+/// Sub-allocates the vertices and indices of many meshes out of one vertex buffer
+/// and one index buffer, so a whole scene can be drawn with a single bind.
+///
+/// The buffers are bound by frames still in flight, so they are never written from the host
+/// nor by a separate submission: `alloc` stages the geometry (and grows the buffers),
+/// `upload` records the copies into the frame, before drawing.
+pub struct MeshArena {
+    vertices: ArenaBuffer<Subbuffer<[PosVertex]>>,
+    indices: ArenaBuffer<Subbuffer<[u32]>>,
+    /// Single zero offset instance, bound when no instance buffer is given
+    pub ibo: VkIterBuffer<PosInstanceData>,
+}
+
+impl MeshArena {
+    pub fn new(vk: Arc<Vk>, vertex_capacity: u32, index_capacity: u32) -> Self {
+        Self {
+            vertices: ArenaBuffer::new(arena_buffer(vk.clone(), BufferUsage::VERTEX_BUFFER, vertex_capacity), vertex_capacity),
+            indices: ArenaBuffer::new(arena_buffer(vk.clone(), BufferUsage::INDEX_BUFFER, index_capacity), index_capacity),
+            ibo: VkIterBuffer::vertex(vk.allocators.clone(), vec![PosInstanceData { ofs: [0.0, 0.0, 0.0] }]),
+        }
+    }
+
+    pub fn vbo(&self) -> &Subbuffer<[PosVertex]> {
+        &self.vertices.buffer
+    }
+
+    pub fn ebo(&self) -> &Subbuffer<[u32]> {
+        &self.indices.buffer
+    }
+
+    /// Stages the geometry for the next `upload`, growing the buffers if there is no free range big enough
+    pub fn alloc(&mut self, vk: Arc<Vk>, vertices: &[PosVertex], indices: &[u32]) -> ArenaMesh {
+        let vertex_range = self.vertices.alloc(vertices.len() as u32, |capacity| {
+            arena_buffer(vk.clone(), BufferUsage::VERTEX_BUFFER, capacity)
+        });
+        let index_range = self.indices.alloc(indices.len() as u32, |capacity| {
+            arena_buffer(vk.clone(), BufferUsage::INDEX_BUFFER, capacity)
+        });
+
+        if let Some(staging) = staging_buffer(vk.clone(), vertices) {
+            self.vertices.write(staging, vertex_range.clone());
+        }
+        if let Some(staging) = staging_buffer(vk, indices) {
+            self.indices.write(staging, index_range.clone());
+        }
+
+        ArenaMesh {
+            vertex_offset: vertex_range.start,
+            vertex_count: vertices.len() as u32,
+            first_index: index_range.start,
+            index_count: indices.len() as u32,
+        }
+    }
+
+    pub fn alloc_mesh(&mut self, vk: Arc<Vk>, mesh: &Mesh) -> ArenaMesh {
+        self.alloc(vk, &mesh.vertices, &mesh.indices)
+    }
+
+    /// Returns the ranges of `mesh` to the arena. The handle must not be drawn afterwards.
+    pub fn free(&mut self, mesh: ArenaMesh) {
+        self.vertices.ranges.free(mesh.vertex_offset..mesh.vertex_offset + mesh.vertex_count);
+        self.indices.ranges.free(mesh.first_index..mesh.first_index + mesh.index_count);
+    }
+
+    /// Records the copies queued since the last call: staged geometry and grown buffers.
+    /// Must be recorded outside of a render pass, before the arena is drawn, and the
+    /// command buffers submitted in the order `upload` was recorded into them
+    pub fn upload(&mut self, builder: &mut BuilderType) {
+        for (src, dst) in self.vertices.take_copies() {
+            copy_slot(builder, src, dst);
+        }
+        for (src, dst) in self.indices.take_copies() {
+            copy_slot(builder, src, dst);
+        }
+    }
+
+    /// (used vertices, used indices)
+    pub fn usage(&self) -> (u32, u32) {
+        (self.vertices.ranges.used(), self.indices.ranges.used())
+    }
+
+    /// Binds the arena buffers with the default single instance at binding 1
+    pub fn bind(&self, builder: &mut BuilderType) {
+        self.bind_with_instances(builder, self.ibo.content.clone());
+    }
+
+    pub fn bind_with_instances(&self, builder: &mut BuilderType, instances: Subbuffer<[PosInstanceData]>) {
+        builder
+            .bind_vertex_buffers(0, (self.vbo().clone(), instances))
+            .unwrap()
+            .bind_index_buffer(self.ebo().clone())
+            .unwrap();
+    }
+
+    /// Warning: this function assumes the arena has already been bound
+    pub fn draw(&self, builder: &mut BuilderType, mesh: &ArenaMesh, instance_count: u32, first_instance: u32) {
+        builder
+            .draw_indexed(
+                mesh.index_count,
+                instance_count,
+                mesh.first_index,
+                mesh.vertex_offset as i32,
+                first_instance
+            )
+            .unwrap();
+    }
+
+    /// Warning: this function assumes a graphics pipeline has already been bounded
+    ///
+    /// Binds the arena once, then for every mesh binds its model matrix the same
+    /// way as `Mesh::build_commands` (set 1, binding 0) and draws it
+    pub fn build_commands<'a>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        pipeline: Arc<GraphicsPipeline>,
+        draws: impl IntoIterator<Item = (&'a ArenaMesh, Mat4)>,
+    ) {
+        self.bind(builder);
+
+        for (mesh, model) in draws {
+            let ubo = VkBuffer::uniform(vk.allocators.clone(), Model {
+                model: model.to_cols_array_2d(),
+                color: [1.0; 4],
+            });
+
+            builder
+                .bind_descriptor_sets(
+                    vulkano::pipeline::PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    1,
+                    descriptor_set(
+                        vk.clone(),
+                        1,
+                        pipeline.clone(),
+                        [WriteDescriptorSet::buffer(0, ubo.content.clone())]
+                    ).0
+                ).unwrap();
+
+            self.draw(builder, mesh, 1, 0);
+        }
+    }
+}
+
+fn arena_buffer<T: BufferContents>(vk: Arc<Vk>, usage: BufferUsage, len: u32) -> Subbuffer<[T]> {
+    Buffer::new_slice(
+        vk.allocators.memory.clone(),
+        BufferCreateInfo {
+            usage: usage | BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+            ..Default::default()
+        },
+        len.max(1) as u64,
+    )
+    .expect("failed to create arena buffer")
+}
+
+fn staging_buffer<T: BufferContents + Copy>(vk: Arc<Vk>, data: &[T]) -> Option<Subbuffer<[T]>> {
+    if data.is_empty() {
+        return None;
+    }
+
+    Some(VkIterBuffer::transfer_src(vk.allocators.clone(), data.iter().copied()).content)
+}
+
+fn copy_slot<T: BufferContents>(builder: &mut BuilderType, src: Slot<Subbuffer<[T]>>, dst: Slot<Subbuffer<[T]>>) {
+    builder
+        .copy_buffer(CopyBufferInfo::buffers(
+            src.buffer.slice(src.range.start as u64..src.range.end as u64),
+            dst.buffer.slice(dst.range.start as u64..dst.range.end as u64),
+        ))
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArenaBuffer, RangeAllocator, Slot};
+
+    #[test]
+    fn alloc_is_first_fit() {
+        let mut ranges = RangeAllocator::new(10);
+
+        assert_eq!(ranges.alloc(4), Some(0..4));
+        assert_eq!(ranges.alloc(4), Some(4..8));
+        assert_eq!(ranges.alloc(4), None);
+        assert_eq!(ranges.alloc(2), Some(8..10));
+        assert_eq!(ranges.used(), 10);
+    }
+
+    #[test]
+    fn free_makes_range_reusable() {
+        let mut ranges = RangeAllocator::new(10);
+        let a = ranges.alloc(4).unwrap();
+        ranges.alloc(4).unwrap();
+
+        ranges.free(a);
+        assert_eq!(ranges.used(), 4);
+        assert_eq!(ranges.alloc(3), Some(0..3));
+    }
+
+    #[test]
+    fn free_merges_neighbours() {
+        let mut ranges = RangeAllocator::new(12);
+        let a = ranges.alloc(4).unwrap();
+        let b = ranges.alloc(4).unwrap();
+        let c = ranges.alloc(4).unwrap();
+
+        ranges.free(a);
+        ranges.free(c);
+        assert_eq!(ranges.alloc(8), None);
+
+        /* b joins a and c into one block */
+        ranges.free(b);
+        assert_eq!(ranges.free, vec![0..12]);
+        assert_eq!(ranges.alloc(12), Some(0..12));
+    }
+
+    #[test]
+    fn grow_merges_with_tail() {
+        let mut ranges = RangeAllocator::new(8);
+        ranges.alloc(6).unwrap();
+
+        ranges.grow(16);
+        assert_eq!(ranges.free, vec![6..16]);
+        assert_eq!(ranges.alloc(10), Some(6..16));
+        assert_eq!(ranges.used(), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "already freed")]
+    fn double_free_panics() {
+        let mut ranges = RangeAllocator::new(8);
+        let a = ranges.alloc(4).unwrap();
+
+        ranges.free(a.clone());
+        ranges.free(a);
+    }
+
+    #[test]
+    #[should_panic(expected = "already freed")]
+    fn overlapping_free_panics() {
+        let mut ranges = RangeAllocator::new(8);
+        ranges.alloc(8).unwrap();
+
+        ranges.free(2..6);
+        ranges.free(4..8);
+    }
+
+    /// Runs `copies` on buffers simulated by `memory`, indexed by id
+    fn run(memory: &mut [Vec<u32>], copies: Vec<(Slot<usize>, Slot<usize>)>) {
+        for (src, dst) in copies {
+            let data = memory[src.buffer][src.range.start as usize..src.range.end as usize].to_vec();
+            memory[dst.buffer][dst.range.start as usize..dst.range.end as usize].copy_from_slice(&data);
+        }
+    }
+
+    #[test]
+    fn grow_after_upload_keeps_uploaded_data() {
+        let mut memory = vec![vec![0; 4]];
+        let mut arena = ArenaBuffer::new(0, 4);
+
+        let a = arena.alloc(4, |_| unreachable!());
+        memory.push(vec![1, 2, 3, 4]);
+        arena.write(1, a);
+        run(&mut memory, arena.take_copies());
+
+        /* the second upload moves the first one into the grown buffer before writing */
+        let b = arena.alloc(4, |capacity| {
+            memory.push(vec![0; capacity as usize]);
+            memory.len() - 1
+        });
+        memory.push(vec![5, 6, 7, 8]);
+        arena.write(memory.len() - 1, b);
+        run(&mut memory, arena.take_copies());
+
+        assert_eq!(memory[arena.buffer], vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn grow_before_upload_keeps_staged_data() {
+        let mut memory = vec![vec![0; 4]];
+        let mut arena = ArenaBuffer::new(0, 4);
+
+        let a = arena.alloc(2, |_| unreachable!());
+        memory.push(vec![1, 2]);
+        arena.write(1, a);
+
+        let b = arena.alloc(4, |capacity| {
+            memory.push(vec![0; capacity as usize]);
+            memory.len() - 1
+        });
+        memory.push(vec![3, 4, 5, 6]);
+        arena.write(memory.len() - 1, b);
+        run(&mut memory, arena.take_copies());
+
+        assert_eq!(arena.ranges.capacity, 8);
+        assert_eq!(memory[arena.buffer][..6], [1, 2, 3, 4, 5, 6]);
+    }
+}
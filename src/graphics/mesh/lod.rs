@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use vulkano::buffer::Subbuffer;
+
+use crate::graphics::{buffer::VkIterBuffer, vertex::{NormalVertex, PosVertex}, vk::Vk};
+
+use super::mesh::smooth_normals;
+
+/// A coarser version of a `Mesh`, drawn while the mesh covers less than `screen_size` of the screen height
+#[derive(Clone)]
+pub struct MeshLod {
+    pub vertices: Vec<PosVertex>,
+    pub normals: Vec<NormalVertex>,
+    pub indices: Vec<u32>,
+    pub screen_size: f32,
+
+    pub vbo: VkIterBuffer<PosVertex>,
+    pub nbo: VkIterBuffer<NormalVertex>,
+    pub ebo: VkIterBuffer<u32>,
+}
+
+impl MeshLod {
+    /// Normals are computed from the triangles, like `Mesh::new`
+    pub fn new(vk: Arc<Vk>, vertices: &[PosVertex], indices: &[u32], screen_size: f32) -> Self {
+        let normals = smooth_normals(vertices, indices);
+
+        Self {
+            vertices: vertices.to_vec(),
+            normals: normals.clone(),
+            indices: indices.to_vec(),
+            screen_size,
+
+            vbo: VkIterBuffer::vertex(vk.allocators.clone(), vertices.to_vec()),
+            nbo: VkIterBuffer::vertex(vk.allocators.clone(), normals),
+            ebo: VkIterBuffer::index(vk.allocators.clone(), indices.to_vec()),
+        }
+    }
+}
+
+/// Instances drawn with the same level, see `Mesh::select_instance_lods`
+#[derive(Clone)]
+pub struct LodGroup {
+    pub level: usize,
+    /// `PosInstanceData` or `ModelInstanceData`, like the instances of the mesh
+    pub instances: Subbuffer<[u8]>,
+    pub count: u32,
+}
+
+/// Levels of detail of a `Mesh`. Level 0 is the mesh itself, level `i` is `levels[i - 1]`.
+#[derive(Clone)]
+pub struct Lods {
+    /// From the finest to the coarsest, with decreasing `screen_size`
+    pub levels: Vec<MeshLod>,
+    /// Fraction of the thresholds the screen size must cross before switching back, against popping
+    pub hysteresis: f32,
+
+    /// Level of the whole mesh, see `Mesh::select_lod`
+    pub current: usize,
+    /// Level of every instance, see `Mesh::select_instance_lods`
+    pub instance_levels: Vec<usize>,
+    /// Drawn in place of the whole mesh at `current` when not empty
+    pub groups: Vec<LodGroup>,
+}
+
+impl Default for Lods {
+    fn default() -> Self {
+        Self {
+            levels: vec![],
+            hysteresis: 0.15,
+
+            current: 0,
+            instance_levels: vec![],
+            groups: vec![],
+        }
+    }
+}
+
+impl Lods {
+    /// Number of levels, including the mesh itself
+    pub fn len(&self) -> usize {
+        self.levels.len() + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Level for `screen_size` (fraction of the screen height) coming from `current`, see `select_level`
+    pub fn level(&self, current: usize, screen_size: f32) -> usize {
+        let thresholds = self.levels
+            .iter()
+            .map(|lod| lod.screen_size)
+            .collect::<Vec<_>>();
+
+        select_level(&thresholds, current, screen_size, self.hysteresis)
+    }
+}
+
+/// Level for `screen_size` coming from `current`: level `i + 1` is used below `thresholds[i]` (decreasing).
+/// Going coarser needs the size `hysteresis` below the threshold, going finer `hysteresis` above it.
+pub fn select_level(thresholds: &[f32], current: usize, screen_size: f32, hysteresis: f32) -> usize {
+    let level_at = |size: f32| thresholds
+        .iter()
+        .take_while(|&&threshold| size < threshold)
+        .count();
+
+    let coarser = level_at(screen_size * (1.0 + hysteresis));
+    let finer = level_at(screen_size * (1.0 - hysteresis));
+
+    if coarser > current {
+        coarser
+    } else if finer < current {
+        finer
+    } else {
+        current.min(thresholds.len())
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use glam::{Vec3, Vec4};
+use vulkano::{buffer::BufferContents, descriptor_set::{layout::{DescriptorSetLayout, DescriptorType}, WriteDescriptorSet}, format::Format, image::view::ImageView};
+
+use crate::graphics::{buffer::VkBuffer, cache::SamplerDesc, image::{ImageSubresource, VkImage}, vk::Vk};
+
+/// Bindings of the material in set 1, next to `Model` at binding 0
+pub const MATERIAL_BINDING: u32 = 1;
+pub const BASE_COLOR_TEXTURE_BINDING: u32 = 2;
+pub const METALLIC_ROUGHNESS_TEXTURE_BINDING: u32 = 3;
+pub const EMISSIVE_TEXTURE_BINDING: u32 = 4;
+
+/// `Material` uniform of the lit shaders
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct MaterialData {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+/// Metallic-roughness material, the Blinn-Phong shader derives its specular from the same values.
+/// Textures multiply their factor, missing ones are replaced by a white texel.
+#[derive(Clone)]
+pub struct Material {
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Added to the lit color, not affected by lights
+    pub emissive: Vec3,
+
+    /// sRGB base color and alpha
+    pub base_color_texture: Option<Arc<ImageView>>,
+    /// glTF layout: roughness in green, metallic in blue
+    pub metallic_roughness_texture: Option<Arc<ImageView>>,
+    pub emissive_texture: Option<Arc<ImageView>>,
+    pub sampler: SamplerDesc,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::ONE,
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: Vec3::ZERO,
+
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            emissive_texture: None,
+            sampler: SamplerDesc::linear(),
+        }
+    }
+}
+
+impl Material {
+    pub fn color(base_color: Vec3) -> Self {
+        Self {
+            base_color: base_color.extend(1.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn metal(base_color: Vec3, roughness: f32) -> Self {
+        Self {
+            base_color: base_color.extend(1.0),
+            metallic: 1.0,
+            roughness,
+            ..Default::default()
+        }
+    }
+
+    pub fn data(&self) -> MaterialData {
+        MaterialData {
+            base_color: self.base_color.to_array(),
+            emissive: self.emissive.extend(1.0).to_array(),
+            metallic: self.metallic,
+            roughness: self.roughness,
+        }
+    }
+
+    /// Writes for the material bindings that `layout` (the `Model` set of the pipeline) declares with the
+    /// material's descriptor type, so pipelines without materials, or with something else at these bindings,
+    /// keep working with `Mesh::build_commands`
+    pub fn writes(&self, vk: Arc<Vk>, layout: &DescriptorSetLayout) -> Vec<WriteDescriptorSet> {
+        let bindings = layout.bindings();
+        let declares = |binding: u32, ty: DescriptorType| bindings
+            .get(&binding)
+            .is_some_and(|declared| declared.descriptor_type == ty);
+        let mut writes = vec![];
+
+        if declares(MATERIAL_BINDING, DescriptorType::UniformBuffer) {
+            let ubo = VkBuffer::uniform(vk.allocators.clone(), self.data());
+            writes.push(WriteDescriptorSet::buffer(MATERIAL_BINDING, ubo.content));
+        }
+
+        let textures = [
+            (BASE_COLOR_TEXTURE_BINDING, &self.base_color_texture),
+            (METALLIC_ROUGHNESS_TEXTURE_BINDING, &self.metallic_roughness_texture),
+            (EMISSIVE_TEXTURE_BINDING, &self.emissive_texture),
+        ];
+
+        for (binding, texture) in textures {
+            if !declares(binding, DescriptorType::CombinedImageSampler) {
+                continue;
+            }
+
+            let view = texture.clone().unwrap_or_else(|| white_texture(vk.clone()));
+            writes.push(WriteDescriptorSet::image_view_sampler(binding, view, vk.samplers.get(self.sampler.clone())));
+        }
+
+        writes
+    }
+}
+
+/// 1x1 white texture, created once per `Vk`
+pub fn white_texture(vk: Arc<Vk>) -> Arc<ImageView> {
+    vk.white_texture
+        .get_or_init(|| {
+            let image = VkImage::sampler_device(vk.allocators.clone(), Format::R8G8B8A8_UNORM, [1, 1, 1], 1);
+            image.upload(vk.clone(), ImageSubresource::default(), &[255u8; 4]);
+
+            ImageView::new_default(image.content).unwrap()
+        })
+        .clone()
+}
@@ -1,56 +1,275 @@
 use std::sync::Arc;
 
-use glam::{Mat4, Quat, Vec3};
-use vulkano::{buffer::BufferContents, descriptor_set::WriteDescriptorSet, pipeline::{GraphicsPipeline, Pipeline}};
+use glam::{Mat4, Quat, Vec3, Vec4};
+use vulkano::{buffer::{BufferContents, Subbuffer}, command_buffer::DrawIndexedIndirectCommand, pipeline::{graphics::vertex_input::VertexInputRate, GraphicsPipeline, Pipeline}};
 
-use crate::graphics::{buffer::{VkBuffer, VkIterBuffer}, command::BuilderType, utils::descriptor_set, vertex::{PosInstanceData, PosVertex}, vk::Vk};
+use crate::graphics::{buffer::{VkBuffer, VkIterBuffer}, camera::Camera, command::BuilderType, culling::{transform_sphere, Bounds, Frustum}, reflect::{BindError, DescriptorWriter}, vertex::{ModelInstanceData, NormalVertex, PosInstanceData, PosVertex}, vk::Vk};
+
+use super::{lod::{LodGroup, Lods, MeshLod}, material::Material};
 
 #[derive(BufferContents, Clone, Copy)]
 #[repr(C)]
 pub struct Model {
-    model: [[f32;4];4]
+    pub model: [[f32;4];4],
+    /// `Mesh::color`, alpha is always 1
+    pub color: [f32; 4],
 }
 
 #[derive(Clone)]
 pub struct Mesh {
     pub vertices: Vec<PosVertex>,
+    /// One per vertex, see `compute_normals`
+    pub normals: Vec<NormalVertex>,
     pub indices: Vec<u32>,
     pub instances: Vec<PosInstanceData>,
+    /// Replaces `instances` when not empty, see `set_instance_transforms`
+    pub model_instances: Vec<ModelInstanceData>,
 
     pub position: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
     pub color: Vec3,
+    pub material: Material,
+    /// Around the vertices and every instance, in model space. See `update_bounds`
+    pub bounds: Bounds,
+    /// Coarser versions of the mesh, see `add_lod`
+    pub lods: Lods,
 
     pub vbo: VkIterBuffer<PosVertex>,
     pub ibo: VkIterBuffer<PosInstanceData>,
+    /// Uploaded `model_instances`, bound at binding 1 in place of `ibo`
+    pub tbo: Option<VkIterBuffer<ModelInstanceData>>,
+    pub nbo: VkIterBuffer<NormalVertex>,
     pub ebo: VkIterBuffer<u32>,
 }
 
 
 impl Mesh {
+    /// Normals are computed from the triangles, see `compute_normals`. Uvs are 0, see `with_uvs`
     pub fn new(vk: Arc<Vk>, vertices: &Vec<PosVertex>, indices: &Vec<u32>) -> Self {
         let instances = vec![PosInstanceData {ofs: [0.0, 0.0, 0.0]}];
+        let normals = smooth_normals(vertices, indices);
 
-        Self {
+        let mut mesh = Self {
             vertices: vertices.to_vec(),
+            normals: normals.clone(),
             indices: indices.to_vec(),
             instances: instances.to_vec(),
+            model_instances: vec![],
 
             position: Vec3::ZERO,
             rotation: Quat::default(),
             scale: Vec3::ONE,
             color: Vec3::ONE,
+            material: Material::default(),
+            bounds: Bounds::default(),
+            lods: Lods::default(),
 
             vbo: VkIterBuffer::vertex(vk.allocators.clone(), vertices.to_vec()),
+            nbo: VkIterBuffer::vertex(vk.allocators.clone(), normals),
             ebo: VkIterBuffer::index(vk.allocators.clone(), indices.to_vec()),
             ibo: VkIterBuffer::vertex(vk.allocators.clone(), instances),
+            tbo: None,
+        };
+        mesh.update_bounds();
+
+        mesh
+    }
+
+    /// `new` with one uv per vertex, for the textures of `material`
+    pub fn with_uvs(vk: Arc<Vk>, vertices: &Vec<PosVertex>, indices: &Vec<u32>, uvs: &[[f32; 2]]) -> Self {
+        let mut mesh = Self::new(vk.clone(), vertices, indices);
+        mesh.set_uvs(uvs);
+        mesh.nbo = VkIterBuffer::vertex(vk.allocators.clone(), mesh.normals.to_vec());
+
+        mesh
+    }
+
+    /// Replaces the uvs of `normals`, one per vertex. Call `rebuild` afterwards.
+    pub fn set_uvs(&mut self, uvs: &[[f32; 2]]) {
+        assert_eq!(uvs.len(), self.vertices.len(), "one uv per vertex");
+
+        self.normals.resize(uvs.len(), NormalVertex::default());
+        for (vertex, uv) in self.normals.iter_mut().zip(uvs) {
+            vertex.uv = *uv;
         }
     }
 
+    /// Uploads `vertices`, `normals` and `indices` again and updates `bounds`
     pub fn rebuild(&mut self, vk: Arc<Vk>) {
         self.vbo = VkIterBuffer::vertex(vk.allocators.clone(), self.vertices.to_vec());
+        self.nbo = VkIterBuffer::vertex(vk.allocators.clone(), self.normals.to_vec());
         self.ebo = VkIterBuffer::index(vk.allocators.clone(), self.indices.to_vec());
+        self.update_bounds();
+    }
+
+    /// Recomputes `bounds` from `vertices` and `instances` or `model_instances`.
+    /// Call it after changing the instances by hand.
+    pub fn update_bounds(&mut self) {
+        let (min, max) = self.vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex.pos))
+            .fold((Vec3::MAX, Vec3::MIN), |(min, max), pos| (min.min(pos), max.max(pos)));
+        let sphere = self.bounding_sphere();
+
+        self.bounds = if self.vertices.is_empty() {
+            Bounds::default()
+        } else {
+            Bounds::from_instances(min, max, sphere, self.instance_transforms())
+        };
+    }
+
+    /// `instances` offsets or `model_instances` transforms, in model space
+    pub fn instance_transforms(&self) -> Vec<Mat4> {
+        if self.model_instances.is_empty() {
+            self.instances
+                .iter()
+                .map(|instance| Mat4::from_translation(instance.ofs.into()))
+                .collect()
+        } else {
+            self.model_instances
+                .iter()
+                .map(|instance| Mat4::from_cols_array_2d(&instance.instance_model))
+                .collect()
+        }
+    }
+
+    /// Adds a coarser level drawn below `screen_size` (fraction of the screen height), levels stay sorted.
+    /// `bounds` keep coming from the finest level.
+    pub fn add_lod(&mut self, vk: Arc<Vk>, vertices: &[PosVertex], indices: &[u32], screen_size: f32) {
+        self.lods.levels.push(MeshLod::new(vk, vertices, indices, screen_size));
+        self.lods.levels.sort_by(|a, b| b.screen_size.total_cmp(&a.screen_size));
+    }
+
+    /// Picks one level for the whole mesh from the screen size of `world_bounds`
+    pub fn select_lod(&mut self, camera: &Camera) -> usize {
+        let sphere = self.world_bounds().sphere;
+        let size = camera.screen_size(sphere.truncate(), sphere.w);
+
+        self.lods.current = self.lods.level(self.lods.current, size);
+        self.lods.groups.clear();
+
+        self.lods.current
+    }
+
+    /// Picks a level per instance and groups the instances by level, one draw per group.
+    /// The groups are only uploaded again when a level changes, call `clear_instance_lods`
+    /// after changing `instances` by hand.
+    pub fn select_instance_lods(&mut self, vk: Arc<Vk>, camera: &Camera) {
+        let model = Mat4::from_cols_array_2d(&self.get_model());
+        let sphere = self.bounding_sphere();
+        let transforms = self.instance_transforms();
+
+        let mut levels = self.lods.instance_levels.clone();
+        levels.resize(transforms.len(), 0);
+        for (level, transform) in levels.iter_mut().zip(transforms) {
+            let world = transform_sphere(model * transform, sphere);
+            let size = camera.screen_size(world.truncate(), world.w);
+
+            *level = self.lods.level(*level, size);
+        }
+
+        if levels == self.lods.instance_levels && !self.lods.groups.is_empty() {
+            return;
+        }
+        self.lods.instance_levels = levels;
+
+        let levels = &self.lods.instance_levels;
+        let at_level = |level: usize| (0..levels.len()).filter(move |&i| levels[i] == level);
+
+        let mut groups = vec![];
+        for level in 0..self.lods.len() {
+            let count = at_level(level).count() as u32;
+            if count == 0 {
+                continue;
+            }
+
+            let instances = if self.model_instances.is_empty() {
+                let data = at_level(level).map(|i| self.instances[i]).collect();
+                VkIterBuffer::<PosInstanceData>::vertex(vk.allocators.clone(), data).content.into_bytes()
+            } else {
+                let data = at_level(level).map(|i| self.model_instances[i]).collect();
+                VkIterBuffer::<ModelInstanceData>::vertex(vk.allocators.clone(), data).content.into_bytes()
+            };
+
+            groups.push(LodGroup { level, instances, count });
+        }
+
+        self.lods.groups = groups;
+    }
+
+    /// Goes back to drawing every instance at `lods.current`
+    pub fn clear_instance_lods(&mut self) {
+        self.lods.instance_levels.clear();
+        self.lods.groups.clear();
+    }
+
+    /// Index count of `level`, 0 being the mesh itself
+    pub fn index_count(&self, level: usize) -> u32 {
+        match level.checked_sub(1).and_then(|i| self.lods.levels.get(i)) {
+            Some(lod) => lod.indices.len() as u32,
+            None => self.indices.len() as u32,
+        }
+    }
+
+    /// `bounds` placed by `position`, `rotation` and `scale`
+    pub fn world_bounds(&self) -> Bounds {
+        self.bounds.transform(Mat4::from_cols_array_2d(&self.get_model()))
+    }
+
+    /// False when no instance can be seen from `frustum`, e.g. `Camera::frustum`
+    pub fn is_visible(&self, frustum: &Frustum) -> bool {
+        self.world_bounds().is_visible(frustum)
+    }
+
+    /// Replaces the normals with smooth ones averaged from the triangles, keeping the uvs of `set_uvs`.
+    /// Call `rebuild` afterwards.
+    pub fn compute_normals(&mut self) {
+        let normals = smooth_normals(&self.vertices, &self.indices);
+
+        self.normals.resize(normals.len(), NormalVertex::default());
+        for (vertex, smooth) in self.normals.iter_mut().zip(normals) {
+            vertex.normal = smooth.normal;
+        }
+    }
+
+    /// Draws one instance per transform, white and with `instance_custom` set to its index.
+    /// The mesh must then be drawn with pipelines taking `ModelInstanceData`, like `utils::model_instancing_pipeline`.
+    pub fn set_instance_transforms(&mut self, vk: Arc<Vk>, transforms: impl IntoIterator<Item = Mat4>) {
+        let instances = transforms
+            .into_iter()
+            .enumerate()
+            .map(|(i, transform)| ModelInstanceData::new(transform, Vec4::ONE, i as u32))
+            .collect();
+
+        self.set_model_instances(vk, instances);
+    }
+
+    /// See `set_instance_transforms`, an empty `instances` goes back to the `PosInstanceData` offsets
+    pub fn set_model_instances(&mut self, vk: Arc<Vk>, instances: Vec<ModelInstanceData>) {
+        self.tbo = if instances.is_empty() {
+            None
+        } else {
+            Some(VkIterBuffer::vertex(vk.allocators.clone(), instances.clone()))
+        };
+        self.model_instances = instances;
+        self.update_bounds();
+        self.clear_instance_lods();
+    }
+
+    /// Goes back to the `PosInstanceData` offsets
+    pub fn clear_model_instances(&mut self) {
+        self.model_instances.clear();
+        self.tbo = None;
+        self.update_bounds();
+        self.clear_instance_lods();
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        match &self.tbo {
+            Some(tbo) => tbo.content.len() as u32,
+            None => self.ibo.content.len() as u32,
+        }
     }
 
     pub fn get_model(&self) -> [[f32; 4]; 4] {
@@ -65,38 +284,176 @@ impl Mesh {
     pub fn get_ubo(&self, vk: Arc<Vk>) -> VkBuffer<Model> {
         VkBuffer::uniform(vk.allocators.clone(), Model {
             model: self.get_model(),
+            color: self.color.extend(1.0).to_array(),
         })
     }
 
     /// Warning: this function assumes a graphics pipeline has already been bounded
     /// 
-    /// On the shaders, it assumes:
+    /// On the shaders, it assumes a block named `Model` (see `shaders/model.glsl`):
     /// ```glsl
-    /// layout(set = 1, binding = 0) uniform Model { 
+    /// uniform Model { 
     ///     mat4 model;
+    ///     vec4 color; // optional
     /// };
     /// ```
-    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) {
-        let ubo = self.get_ubo(vk.clone());
+    /// found through the reflection of the pipeline (see `bind_model`),
+    /// and binds `material` at the bindings 1 to 4 of the same set the pipeline declares, see `Material::writes`.
+    /// Vertex buffers are `PosVertex`, `PosInstanceData` and `NormalVertex` at bindings 0, 1 and 2,
+    /// or `ModelInstanceData` at binding 1 once `tbo` is set (see `instance_buffer`).
+    ///
+    /// Draws `lods.current`, or one draw per group after `select_instance_lods`.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) -> Result<(), BindError> {
+        let instances = self.instance_buffer(&pipeline)?;
+        self.bind_model(vk, builder, pipeline)?;
+
+        if self.lods.groups.is_empty() {
+            self.draw_level(builder, self.lods.current, instances, self.instance_count());
+        } else {
+            for group in &self.lods.groups {
+                self.draw_level(builder, group.level, group.instances.clone(), group.count);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `build_commands` with `instances` at binding 1 and the instance count read from `indirect`,
+    /// e.g. written by `culling::GpuCulling`
+    pub fn build_commands_indirect<I: ?Sized>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        pipeline: Arc<GraphicsPipeline>,
+        instances: Subbuffer<I>,
+        indirect: Subbuffer<[DrawIndexedIndirectCommand]>,
+    ) -> Result<(), BindError> {
+        self.bind_model(vk, builder, pipeline)?;
+
         builder
-            .bind_descriptor_sets(
-                vulkano::pipeline::PipelineBindPoint::Graphics, 
-                pipeline.layout().clone(), 
-                1, 
-                descriptor_set(
-                    vk.clone(), 
-                    1,
-                    pipeline.clone(), 
-                    [WriteDescriptorSet::buffer(0, ubo.content.clone())]
-                ).0
-            ).unwrap()
             .bind_vertex_buffers(0, 
-                (self.vbo.content.clone(), self.ibo.content.clone())
+                (self.vbo.content.clone(), instances, self.nbo.content.clone())
             )
             .unwrap()
             .bind_index_buffer(self.ebo.content.clone())
             .unwrap()
-            .draw_indexed(self.ebo.content.len() as u32, self.ibo.content.len() as u32, 0, 0, 0)
+            .draw_indexed_indirect(indirect)
             .unwrap();
+
+        Ok(())
+    }
+
+    /// `tbo` or `ibo`, whichever matches the stride of the binding 1 of `pipeline`.
+    ///
+    /// Fails when the pipeline takes the other instance type per instance, e.g. a mesh with `set_instance_transforms`
+    /// drawn with a `PosInstanceData` pipeline would read its transforms as offsets.
+    /// A per-vertex binding 1 is left to the caller.
+    pub fn instance_buffer(&self, pipeline: &GraphicsPipeline) -> Result<Subbuffer<[u8]>, BindError> {
+        let (instances, stride, name) = match &self.tbo {
+            Some(tbo) => (tbo.content.clone().into_bytes(), size_of::<ModelInstanceData>(), "ModelInstanceData"),
+            None => (self.ibo.content.clone().into_bytes(), size_of::<PosInstanceData>(), "PosInstanceData"),
+        };
+
+        if let Some(binding) = pipeline.vertex_input_state().bindings.get(&1) {
+            if matches!(binding.input_rate, VertexInputRate::Instance { .. }) && binding.stride as usize != stride {
+                return Err(BindError::InstanceStride { instances: name, stride: binding.stride });
+            }
+        }
+
+        Ok(instances)
     }
+
+    /// Sphere around the vertices in model space, as center and radius
+    pub fn bounding_sphere(&self) -> Vec4 {
+        bounding_sphere(&self.vertices)
+    }
+
+    /// Binds the buffers of `level` with `instances` at binding 1 and draws them
+    fn draw_level(&self, builder: &mut BuilderType, level: usize, instances: Subbuffer<[u8]>, instance_count: u32) {
+        let (vbo, nbo, ebo) = match level.checked_sub(1).and_then(|i| self.lods.levels.get(i)) {
+            Some(lod) => (&lod.vbo, &lod.nbo, &lod.ebo),
+            None => (&self.vbo, &self.nbo, &self.ebo),
+        };
+
+        builder
+            .bind_vertex_buffers(0, 
+                (vbo.content.clone(), instances, nbo.content.clone())
+            )
+            .unwrap()
+            .bind_index_buffer(ebo.content.clone())
+            .unwrap()
+            .draw_indexed(ebo.content.len() as u32, instance_count, 0, 0, 0)
+            .unwrap();
+    }
+
+    /// Binds the model and material set, see `build_commands`.
+    ///
+    /// `Model` is looked up by name in the reflection registered for the pipeline in `Vk::reflections`,
+    /// see `GraphicsPipelineBuilder::reflect`.
+    pub fn bind_model(&self, vk: Arc<Vk>, builder: &mut BuilderType, pipeline: Arc<GraphicsPipeline>) -> Result<(), BindError> {
+        let reflection = vk.reflections
+            .get(pipeline.layout())
+            .ok_or(BindError::NoReflection)?;
+        let set = reflection
+            .get("Model")
+            .ok_or_else(|| BindError::UnknownName("Model".to_string()))?
+            .set;
+        let set_layout = pipeline.layout()
+            .set_layouts()
+            .get(set as usize)
+            .ok_or_else(|| BindError::NotInLayout("Model".to_string()))?;
+
+        let ubo = self.get_ubo(vk.clone());
+        let material = self.material.writes(vk.clone(), set_layout);
+
+        material
+            .into_iter()
+            .fold(DescriptorWriter::new(pipeline.clone(), &reflection), |writer, write| writer.write(set, write))
+            .buffer("Model", ubo.content.clone())?
+            .bind(vk, builder)
+    }
+}
+
+
+/// Area weighted average of the normals of the triangles around every vertex
+pub fn smooth_normals(vertices: &[PosVertex], indices: &[u32]) -> Vec<NormalVertex> {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| Vec3::from(vertices[i as usize].pos));
+        /* not normalized, bigger triangles weigh more */
+        let normal = (b - a).cross(c - a);
+
+        for &i in triangle {
+            normals[i as usize] += normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| NormalVertex {
+            normal: normal.normalize_or_zero().to_array(),
+            uv: [0.0, 0.0],
+        })
+        .collect()
 }
+
+/// Sphere centered on the bounding box of `vertices`, as center and radius
+pub fn bounding_sphere(vertices: &[PosVertex]) -> Vec4 {
+    if vertices.is_empty() {
+        return Vec4::ZERO;
+    }
+
+    let (min, max) = vertices
+        .iter()
+        .map(|vertex| Vec3::from(vertex.pos))
+        .fold((Vec3::MAX, Vec3::MIN), |(min, max), pos| (min.min(pos), max.max(pos)));
+
+    let center = (min + max) * 0.5;
+    let radius = vertices
+        .iter()
+        .map(|vertex| Vec3::from(vertex.pos).distance(center))
+        .fold(0.0, f32::max);
+
+    center.extend(radius)
+}
\ No newline at end of file
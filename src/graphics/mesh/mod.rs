@@ -1 +1,5 @@
 pub mod mesh;
+pub mod arena;
+pub mod material;
+pub mod lod;
+pub mod shaders;
\ No newline at end of file
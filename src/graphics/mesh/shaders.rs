@@ -0,0 +1,19 @@
+/// Unlit `Mesh` shaders for `ModelInstanceData`, see `utils::model_instancing_pipeline`
+pub mod instanced_vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/unlit_instanced.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/unlit_instanced.vert");
+}
+
+pub mod instanced_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        path: "src/graphics/shaders/unlit_instanced.frag",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/unlit_instanced.frag");
+}
@@ -7,3 +7,20 @@ pub mod vertex;
 pub mod camera;
 pub mod mesh;
 pub mod image;
+pub mod cache;
+pub mod skybox;
+pub mod bindless;
+pub mod pipeline;
+pub mod hot_reload;
+pub mod reflect;
+pub mod compute;
+pub mod deferred;
+pub mod post;
+pub mod shadow;
+pub mod lighting;
+pub mod debug;
+pub mod sprite;
+pub mod culling;
+pub mod stats;
+pub mod terrain;
+pub mod animation;
\ No newline at end of file
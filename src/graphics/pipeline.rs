@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use vulkano::{image::SampleCount, pipeline::{graphics::{color_blend::{AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState}, depth_stencil::{CompareOp, DepthState, DepthStencilState, StencilState}, input_assembly::{InputAssemblyState, PrimitiveTopology}, multisample::MultisampleState, rasterization::{CullMode, DepthBiasState, FrontFace, PolygonMode, RasterizationState}, vertex_input::{Vertex, VertexBufferDescription, VertexDefinition, VertexInputState}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, DynamicState, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{RenderPass, Subpass}, shader::ShaderModule};
+
+use super::{hot_reload::ShaderKind, vk::Vk};
+
+/// Color blending applied to every color attachment of the subpass
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BlendPreset {
+    #[default]
+    Opaque,
+    /// src * src_alpha + dst * (1 - src_alpha)
+    Alpha,
+    /// src + dst * (1 - src_alpha), for colors already multiplied by their alpha
+    Premultiplied,
+    /// src + dst
+    Additive,
+}
+
+impl BlendPreset {
+    fn attachment_blend(self) -> Option<AttachmentBlend> {
+        match self {
+            BlendPreset::Opaque => None,
+            BlendPreset::Alpha => Some(AttachmentBlend::alpha()),
+            BlendPreset::Premultiplied => Some(AttachmentBlend {
+                src_color_blend_factor: BlendFactor::One,
+                dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                color_blend_op: BlendOp::Add,
+                src_alpha_blend_factor: BlendFactor::One,
+                dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                alpha_blend_op: BlendOp::Add,
+            }),
+            BlendPreset::Additive => Some(AttachmentBlend::additive()),
+        }
+    }
+}
+
+/// Builds graphics pipelines out of a vertex and an optional fragment shader.
+///
+/// Defaults match the old `utils::pipeline`: triangle lists, no culling, fill,
+/// opaque blending, depth test and write with `Less` (when the subpass has a depth attachment),
+/// the sample count of the subpass and subpass 0.
+///
+/// ```ignore
+/// let pipeline = GraphicsPipelineBuilder::new(vs, fs)
+///     .vertex::<PosVertex>()
+///     .instance::<PosInstanceData>()
+///     .cull_mode(CullMode::Back)
+///     .blend(BlendPreset::Alpha)
+///     .dynamic_state(DynamicState::Viewport)
+///     .build(vk.clone(), render_pass.clone());
+/// ```
+#[derive(Clone)]
+pub struct GraphicsPipelineBuilder {
+    vs: Arc<ShaderModule>,
+    fs: Option<Arc<ShaderModule>>,
+    vertex_buffers: Vec<VertexBufferDescription>,
+
+    topology: PrimitiveTopology,
+    cull_mode: CullMode,
+    front_face: FrontFace,
+    polygon_mode: PolygonMode,
+    line_width: f32,
+    depth_bias: Option<DepthBiasState>,
+
+    depth: Option<DepthState>,
+    stencil: Option<StencilState>,
+
+    blend: BlendPreset,
+    samples: Option<SampleCount>,
+    dynamic_states: Vec<DynamicState>,
+    viewport: Option<Viewport>,
+    subpass: u32,
+    layout: Option<Arc<PipelineLayout>>,
+    /// GLSL of the stages, see `reflect`
+    glsl: Vec<(&'static str, ShaderKind)>,
+}
+
+impl GraphicsPipelineBuilder {
+    /// `fs` can be `None` for depth only passes
+    pub fn new(vs: Arc<ShaderModule>, fs: impl Into<Option<Arc<ShaderModule>>>) -> Self {
+        Self {
+            vs,
+            fs: fs.into(),
+            vertex_buffers: Vec::new(),
+
+            topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            polygon_mode: PolygonMode::Fill,
+            line_width: 1.0,
+            depth_bias: None,
+
+            depth: Some(DepthState::simple()),
+            stencil: None,
+
+            blend: BlendPreset::Opaque,
+            samples: None,
+            dynamic_states: Vec::new(),
+            viewport: None,
+            subpass: 0,
+            layout: None,
+            glsl: Vec::new(),
+        }
+    }
+
+    /// Adds a per-vertex buffer, bound at the next binding
+    pub fn vertex<V: Vertex>(mut self) -> Self {
+        self.vertex_buffers.push(V::per_vertex());
+        self
+    }
+
+    /// Adds a per-instance buffer, bound at the next binding
+    pub fn instance<I: Vertex>(mut self) -> Self {
+        self.vertex_buffers.push(I::per_instance());
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullMode) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    /// `Line` and `Point` need the `fill_mode_non_solid` feature
+    pub fn polygon_mode(mut self, polygon_mode: PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Anything other than 1.0 needs the `wide_lines` feature
+    pub fn line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    /// Offsets the written depth by `constant_factor` units plus `slope_factor` times the polygon slope,
+    /// mostly to avoid acne in shadow maps
+    pub fn depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.depth_bias = Some(DepthBiasState {
+            constant_factor,
+            clamp: 0.0,
+            slope_factor,
+        });
+        self
+    }
+
+    pub fn depth_test(mut self, enable: bool) -> Self {
+        self.depth = match (enable, self.depth) {
+            (true, None) => Some(DepthState::simple()),
+            (true, depth) => depth,
+            (false, _) => None,
+        };
+        self
+    }
+
+    /// Ignored when the depth test is disabled
+    pub fn depth_write(mut self, enable: bool) -> Self {
+        if let Some(depth) = self.depth.as_mut() {
+            depth.write_enable = enable;
+        }
+        self
+    }
+
+    /// Ignored when the depth test is disabled
+    pub fn depth_compare(mut self, compare_op: CompareOp) -> Self {
+        if let Some(depth) = self.depth.as_mut() {
+            depth.compare_op = compare_op;
+        }
+        self
+    }
+
+    pub fn stencil(mut self, stencil: StencilState) -> Self {
+        self.stencil = Some(stencil);
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendPreset) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Defaults to the sample count of the subpass attachments
+    pub fn samples(mut self, samples: SampleCount) -> Self {
+        self.samples = Some(samples);
+        self
+    }
+
+    pub fn dynamic_state(mut self, state: DynamicState) -> Self {
+        self.dynamic_states.push(state);
+        self
+    }
+
+    /// Fixed viewport, leave unset when the viewport is a dynamic state
+    pub fn viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    /// Uses `layout` instead of one derived from the shaders, e.g. to share sets between pipelines
+    pub fn layout(mut self, layout: Arc<PipelineLayout>) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// GLSL the shaders were loaded from, like `lighting::shaders::vs::SOURCE`. `build` reflects it into
+    /// `Vk::reflections`, which is where `Mesh::bind_model` finds the `Model` block.
+    /// A failed reflection is logged and leaves the pipeline without one.
+    pub fn reflect(mut self, vs: &'static str, fs: impl Into<Option<&'static str>>) -> Self {
+        self.glsl = [(vs, ShaderKind::Vertex)]
+            .into_iter()
+            .chain(fs.into().map(|fs| (fs, ShaderKind::Fragment)))
+            .collect();
+        self
+    }
+
+    pub fn build(self, vk: Arc<Vk>, render_pass: Arc<RenderPass>) -> Arc<GraphicsPipeline> {
+        let vs = self.vs.entry_point("main").unwrap();
+
+        let vertex_input_state = if self.vertex_buffers.is_empty() {
+            VertexInputState::new()
+        } else {
+            self.vertex_buffers
+                .definition(&vs.info().input_interface)
+                .unwrap()
+        };
+
+        let mut stages = vec![PipelineShaderStageCreateInfo::new(vs)];
+        if let Some(fs) = &self.fs {
+            stages.push(PipelineShaderStageCreateInfo::new(fs.entry_point("main").unwrap()));
+        }
+
+        let layout = self.layout.clone().unwrap_or_else(|| {
+            PipelineLayout::new(
+                vk.device.clone(),
+                PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+                    .into_pipeline_layout_create_info(vk.device.clone())
+                    .unwrap(),
+            )
+            .unwrap()
+        });
+
+        let subpass = Subpass::from(render_pass, self.subpass).unwrap();
+
+        let with_count = self.dynamic_states.iter().any(|state| {
+            matches!(state, DynamicState::ViewportWithCount | DynamicState::ScissorWithCount)
+        });
+        let viewport_state = match self.viewport {
+            _ if with_count => ViewportState {
+                viewports: Default::default(),
+                scissors: Default::default(),
+                ..Default::default()
+            },
+            Some(viewport) => ViewportState {
+                viewports: [viewport].into_iter().collect(),
+                ..Default::default()
+            },
+            None => ViewportState::default(),
+        };
+
+        let depth_stencil_state = subpass.subpass_desc().depth_stencil_attachment.is_some().then(|| {
+            DepthStencilState {
+                depth: self.depth,
+                stencil: self.stencil.clone(),
+                ..Default::default()
+            }
+        });
+
+        let rasterization_samples = self.samples
+            .or(subpass.num_samples())
+            .unwrap_or(SampleCount::Sample1);
+
+        let pipeline = GraphicsPipeline::new(
+            vk.device.clone(),
+            Some(vk.pipeline_cache.cache.clone()),
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState {
+                    topology: self.topology,
+                    ..Default::default()
+                }),
+                viewport_state: Some(viewport_state),
+                rasterization_state: Some(RasterizationState {
+                    cull_mode: self.cull_mode,
+                    front_face: self.front_face,
+                    polygon_mode: self.polygon_mode,
+                    line_width: self.line_width,
+                    depth_bias: self.depth_bias,
+                    ..Default::default()
+                }),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples,
+                    ..Default::default()
+                }),
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState {
+                        blend: self.blend.attachment_blend(),
+                        ..Default::default()
+                    },
+                )),
+                depth_stencil_state,
+                dynamic_state: self.dynamic_states.into_iter().collect(),
+                subpass: Some(subpass.into()),
+                ..GraphicsPipelineCreateInfo::layout(layout)
+            },
+        )
+        .unwrap();
+
+        if !self.glsl.is_empty() {
+            if let Err(e) = vk.reflections.insert_glsl(pipeline.layout(), &self.glsl) {
+                println!("failed to reflect the shaders of a pipeline: {e}");
+            }
+        }
+
+        pipeline
+    }
+}
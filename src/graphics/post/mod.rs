@@ -0,0 +1,496 @@
+use std::sync::Arc;
+
+use imgui::Ui;
+use vulkano::{buffer::BufferContents, command_buffer::{RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::WriteDescriptorSet, format::{ClearValue, Format}, image::{sampler::{Sampler, SamplerAddressMode}, view::ImageView, Image}, pipeline::{graphics::viewport::Viewport, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}, shader::ShaderModule};
+
+use super::{cache::SamplerDesc, command::BuilderType, image::VkImage, pipeline::{BlendPreset, GraphicsPipelineBuilder}, utils::descriptor_set, vk::Vk};
+
+pub mod shaders;
+
+/// Format of the offscreen scene target and the bloom pyramid
+pub const HDR_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+/// Bloom levels, the first one at half resolution
+pub const BLOOM_LEVELS: usize = 5;
+
+/// Toggles and parameters of the built-in effects
+#[derive(Clone, Copy, Debug)]
+pub struct PostSettings {
+    /// ACES filmic curve, clamps the scene to [0, 1] when off
+    pub tonemap: bool,
+    pub exposure: f32,
+
+    pub bloom: bool,
+    /// Brightness above which pixels bloom
+    pub bloom_threshold: f32,
+    /// Width of the soft transition around the threshold
+    pub bloom_knee: f32,
+    pub bloom_intensity: f32,
+    /// Spread of the upsampling filter, in texels
+    pub bloom_radius: f32,
+
+    pub fxaa: bool,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            tonemap: true,
+            exposure: 1.0,
+
+            bloom: true,
+            bloom_threshold: 1.0,
+            bloom_knee: 0.5,
+            bloom_intensity: 0.05,
+            bloom_radius: 1.0,
+
+            fxaa: true,
+        }
+    }
+}
+
+/// Push constants given to custom effects, if their shader declares them:
+/// `layout(push_constant) uniform CustomPC { vec2 texel_size; float time; };`
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct CustomPC {
+    pub texel_size: [f32; 2],
+    pub time: f32,
+}
+
+/// A user supplied fragment shader, run after tonemapping and before FXAA
+pub struct CustomEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub pipeline: Arc<GraphicsPipeline>,
+}
+
+struct BloomLevel {
+    view: Arc<ImageView>,
+    extent: [u32; 2],
+    /// Overwrites the level (threshold, downsampling)
+    framebuffer: Arc<Framebuffer>,
+    /// Adds to the level (upsampling)
+    add_framebuffer: Arc<Framebuffer>,
+}
+
+struct Targets {
+    extent: [u32; 2],
+    scene: Arc<ImageView>,
+    scene_framebuffer: Arc<Framebuffer>,
+    bloom: Vec<BloomLevel>,
+    /// Ping-pong targets between two effects, in the swapchain format
+    ldr: [(Arc<ImageView>, Arc<Framebuffer>); 2],
+    outputs: Vec<Arc<Framebuffer>>,
+}
+
+/// The scene is drawn into an HDR target (`scene_pass`, `scene_framebuffer`), then
+/// `build_commands` runs bloom, tonemapping, the custom effects and FXAA into the swapchain image.
+/// Every effect can be switched off with `settings`, custom effects with their `enabled` flag.
+///
+/// ```ignore
+/// let mut post = PostProcess::new(vk.clone(), presenter.swapchain.image_format());
+/// post.resize(vk.clone(), &presenter.images);
+/// let pipeline = GraphicsPipelineBuilder::new(vs, fs).build(vk.clone(), post.scene_pass.clone());
+///
+/// // every frame
+/// builder.begin_render_pass(RenderPassBeginInfo {
+///     clear_values: post.clear_values(),
+///     ..RenderPassBeginInfo::framebuffer(post.scene_framebuffer())
+/// }, ..);
+/// // draw the scene
+/// builder.end_render_pass(..);
+/// post.build_commands(vk.clone(), &mut builder, image_i);
+/// // ImGui
+/// ```
+pub struct PostProcess {
+    /// HDR color and depth, draw the scene with pipelines built on this
+    pub scene_pass: Arc<RenderPass>,
+    bloom_pass: Arc<RenderPass>,
+    bloom_add_pass: Arc<RenderPass>,
+    /// Single color attachment in the swapchain format, custom effects are built on this
+    pub output_pass: Arc<RenderPass>,
+
+    threshold_pipeline: Arc<GraphicsPipeline>,
+    downsample_pipeline: Arc<GraphicsPipeline>,
+    upsample_pipeline: Arc<GraphicsPipeline>,
+    tonemap_pipeline: Arc<GraphicsPipeline>,
+    fxaa_pipeline: Arc<GraphicsPipeline>,
+    pub custom: Vec<CustomEffect>,
+
+    sampler: Arc<Sampler>,
+    targets: Option<Targets>,
+
+    pub settings: PostSettings,
+    pub background: [f32; 4],
+    /// Given to custom effects, see `CustomPC`
+    pub time: f32,
+}
+
+impl PostProcess {
+    /// `format` is the swapchain image format. Call `resize` before drawing.
+    pub fn new(vk: Arc<Vk>, format: Format) -> Self {
+        let scene_pass = vulkano::single_pass_renderpass!(vk.device.clone(),
+            attachments: {
+                color: {
+                    format: HDR_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap();
+
+        let bloom_pass = color_pass(vk.clone(), HDR_FORMAT, false);
+        let bloom_add_pass = color_pass(vk.clone(), HDR_FORMAT, true);
+        let output_pass = color_pass(vk.clone(), format, false);
+
+        let vs = shaders::vs::load(vk.device.clone()).unwrap();
+        let fullscreen = |fs: Arc<ShaderModule>, blend: BlendPreset, render_pass: &Arc<RenderPass>| {
+            GraphicsPipelineBuilder::new(vs.clone(), fs)
+                .blend(blend)
+                .dynamic_state(DynamicState::Viewport)
+                .build(vk.clone(), render_pass.clone())
+        };
+
+        let threshold_pipeline = fullscreen(shaders::threshold_fs::load(vk.device.clone()).unwrap(), BlendPreset::Opaque, &bloom_pass);
+        let downsample_pipeline = fullscreen(shaders::downsample_fs::load(vk.device.clone()).unwrap(), BlendPreset::Opaque, &bloom_pass);
+        let upsample_pipeline = fullscreen(shaders::upsample_fs::load(vk.device.clone()).unwrap(), BlendPreset::Additive, &bloom_add_pass);
+        let tonemap_pipeline = fullscreen(shaders::tonemap_fs::load(vk.device.clone()).unwrap(), BlendPreset::Opaque, &output_pass);
+        let fxaa_pipeline = fullscreen(shaders::fxaa_fs::load(vk.device.clone()).unwrap(), BlendPreset::Opaque, &output_pass);
+
+        let sampler = vk.samplers.get(SamplerDesc::linear().address_mode(SamplerAddressMode::ClampToEdge));
+
+        Self {
+            scene_pass,
+            bloom_pass,
+            bloom_add_pass,
+            output_pass,
+
+            threshold_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            tonemap_pipeline,
+            fxaa_pipeline,
+            custom: vec![],
+
+            sampler,
+            targets: None,
+
+            settings: PostSettings::default(),
+            background: [0.1, 0.2, 0.3, 1.0],
+            time: 0.0,
+        }
+    }
+
+    /// Adds a custom effect at the end of the custom chain and returns its index.
+    ///
+    /// `fs` gets `layout(location = 0) in vec2 uv;` and the previous result in
+    /// `layout(set = 0, binding = 0) uniform sampler2D u_input;`, see `CustomPC` for the push constants
+    pub fn add_custom(&mut self, vk: Arc<Vk>, name: impl Into<String>, fs: Arc<ShaderModule>) -> usize {
+        let pipeline = GraphicsPipelineBuilder::new(shaders::vs::load(vk.device.clone()).unwrap(), fs)
+            .dynamic_state(DynamicState::Viewport)
+            .build(vk, self.output_pass.clone());
+
+        self.custom.push(CustomEffect {
+            name: name.into(),
+            enabled: true,
+            pipeline,
+        });
+
+        self.custom.len() - 1
+    }
+
+    /// (Re)creates the offscreen targets and one output framebuffer per swapchain image
+    pub fn resize(&mut self, vk: Arc<Vk>, images: &[Arc<Image>]) {
+        let extent = images[0].extent();
+        let format = self.output_pass.attachments()[0].format;
+
+        let scene = ImageView::new_default(VkImage::render_target(vk.allocators.clone(), HDR_FORMAT, extent).content).unwrap();
+        let depth = ImageView::new_default(VkImage::depth(vk.allocators.clone(), DEPTH_FORMAT, extent).content).unwrap();
+        let scene_framebuffer = framebuffer(self.scene_pass.clone(), vec![scene.clone(), depth]);
+
+        let mut bloom = vec![];
+        let mut level_extent = [extent[0], extent[1]];
+        for _ in 0..BLOOM_LEVELS {
+            level_extent = [level_extent[0] / 2, level_extent[1] / 2];
+            if level_extent[0] == 0 || level_extent[1] == 0 {
+                break;
+            }
+
+            let view = ImageView::new_default(
+                VkImage::render_target(vk.allocators.clone(), HDR_FORMAT, [level_extent[0], level_extent[1], 1]).content
+            )
+            .unwrap();
+
+            bloom.push(BloomLevel {
+                framebuffer: framebuffer(self.bloom_pass.clone(), vec![view.clone()]),
+                add_framebuffer: framebuffer(self.bloom_add_pass.clone(), vec![view.clone()]),
+                view,
+                extent: level_extent,
+            });
+        }
+
+        let ldr = [(); 2].map(|_| {
+            let view = ImageView::new_default(VkImage::render_target(vk.allocators.clone(), format, extent).content).unwrap();
+            (view.clone(), framebuffer(self.output_pass.clone(), vec![view]))
+        });
+
+        let outputs = images
+            .iter()
+            .map(|image| framebuffer(self.output_pass.clone(), vec![vk.image_views.get_default(image)]))
+            .collect();
+
+        self.targets = Some(Targets {
+            extent: [extent[0], extent[1]],
+            scene,
+            scene_framebuffer,
+            bloom,
+            ldr,
+            outputs,
+        });
+    }
+
+    /// Extent of the targets, `None` before the first `resize`
+    pub fn extent(&self) -> Option<[u32; 2]> {
+        self.targets.as_ref().map(|targets| targets.extent)
+    }
+
+    /// Framebuffer of `scene_pass`, shared by every swapchain image
+    pub fn scene_framebuffer(&self) -> Arc<Framebuffer> {
+        self.targets().scene_framebuffer.clone()
+    }
+
+    /// For `scene_framebuffer`
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        vec![Some(self.background.into()), Some(1.0.into())]
+    }
+
+    /// Records every enabled effect, reading the scene target and writing the swapchain image `image_i`.
+    /// Must be recorded after the scene pass and outside of any render pass.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, image_i: usize) {
+        let targets = self.targets();
+        let settings = &self.settings;
+
+        /* bloom: threshold into the first level, down the pyramid, then back up adding every level */
+        let bloom = if settings.bloom && !targets.bloom.is_empty() {
+            let levels = &targets.bloom;
+
+            self.fullscreen_pass(vk.clone(), builder, &self.threshold_pipeline, levels[0].framebuffer.clone(), [targets.scene.clone()], shaders::threshold_fs::ThresholdPC {
+                threshold: settings.bloom_threshold,
+                knee: settings.bloom_knee,
+            });
+
+            for i in 1..levels.len() {
+                self.fullscreen_pass(vk.clone(), builder, &self.downsample_pipeline, levels[i].framebuffer.clone(), [levels[i - 1].view.clone()], shaders::downsample_fs::DownsamplePC {
+                    texel_size: texel_size(levels[i - 1].extent),
+                });
+            }
+
+            for i in (1..levels.len()).rev() {
+                self.fullscreen_pass(vk.clone(), builder, &self.upsample_pipeline, levels[i - 1].add_framebuffer.clone(), [levels[i].view.clone()], shaders::upsample_fs::UpsamplePC {
+                    texel_size: texel_size(levels[i].extent),
+                    radius: settings.bloom_radius,
+                });
+            }
+
+            Some(levels[0].view.clone())
+        } else {
+            None
+        };
+
+        /* the last enabled effect writes the swapchain image, the others alternate between the ldr targets */
+        let custom = self.custom
+            .iter()
+            .filter(|effect| effect.enabled)
+            .collect::<Vec<_>>();
+        let passes = 1 + custom.len() + settings.fxaa as usize;
+
+        let target = |pass: usize| if pass + 1 == passes {
+            (targets.outputs[image_i].clone(), None)
+        } else {
+            (targets.ldr[pass % 2].1.clone(), Some(targets.ldr[pass % 2].0.clone()))
+        };
+
+        let (framebuffer, mut input) = target(0);
+        self.fullscreen_pass(
+            vk.clone(),
+            builder,
+            &self.tonemap_pipeline,
+            framebuffer,
+            [targets.scene.clone(), bloom.clone().unwrap_or(targets.scene.clone())],
+            shaders::tonemap_fs::TonemapPC {
+                exposure: settings.exposure,
+                bloom_intensity: if bloom.is_some() { settings.bloom_intensity } else { 0.0 },
+                tonemap: settings.tonemap as u32,
+            },
+        );
+
+        for (i, effect) in custom.iter().enumerate() {
+            let (framebuffer, output) = target(i + 1);
+            let push_constants = (!effect.pipeline.layout().push_constant_ranges().is_empty()).then_some(CustomPC {
+                texel_size: texel_size(targets.extent),
+                time: self.time,
+            });
+
+            self.fullscreen_pass::<CustomPC>(vk.clone(), builder, &effect.pipeline, framebuffer, input.take(), push_constants);
+            input = output;
+        }
+
+        if settings.fxaa {
+            let (framebuffer, _) = target(passes - 1);
+
+            self.fullscreen_pass(vk.clone(), builder, &self.fxaa_pipeline, framebuffer, input.take(), shaders::fxaa_fs::FxaaPC {
+                texel_size: texel_size(targets.extent),
+            });
+        }
+    }
+
+    /// Checkboxes and sliders for every effect
+    pub fn ui(&mut self, ui: &Ui) {
+        ui.window("post-processing")
+            .size([280.0, 300.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let settings = &mut self.settings;
+
+                ui.checkbox("tonemap", &mut settings.tonemap);
+                ui.slider("exposure", 0.05, 8.0, &mut settings.exposure);
+                ui.separator();
+
+                ui.checkbox("bloom", &mut settings.bloom);
+                ui.slider("threshold", 0.0, 4.0, &mut settings.bloom_threshold);
+                ui.slider("knee", 0.0, 1.0, &mut settings.bloom_knee);
+                ui.slider("intensity", 0.0, 1.0, &mut settings.bloom_intensity);
+                ui.slider("radius", 0.5, 4.0, &mut settings.bloom_radius);
+                ui.separator();
+
+                for effect in &mut self.custom {
+                    ui.checkbox(&effect.name, &mut effect.enabled);
+                }
+
+                ui.checkbox("fxaa", &mut settings.fxaa);
+            });
+    }
+
+    fn targets(&self) -> &Targets {
+        self.targets.as_ref().expect("call resize before drawing")
+    }
+
+    /// One fullscreen triangle into `framebuffer`, sampling `inputs` at bindings 0, 1, ...
+    fn fullscreen_pass<Pc: BufferContents>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        pipeline: &Arc<GraphicsPipeline>,
+        framebuffer: Arc<Framebuffer>,
+        inputs: impl IntoIterator<Item = Arc<ImageView>>,
+        push_constants: impl Into<Option<Pc>>,
+    ) {
+        let extent = framebuffer.extent();
+        let set = descriptor_set(
+            vk,
+            0,
+            pipeline.clone(),
+            inputs
+                .into_iter()
+                .enumerate()
+                .map(|(binding, view)| WriteDescriptorSet::image_view_sampler(binding as u32, view, self.sampler.clone())),
+        ).0;
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .set_viewport(0, [Viewport {
+                offset: [0.0, 0.0],
+                extent: [extent[0] as f32, extent[1] as f32],
+                depth_range: 0.0..=1.0,
+            }].into_iter().collect())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, set)
+            .unwrap();
+
+        if let Some(push_constants) = push_constants.into() {
+            builder
+                .push_constants(pipeline.layout().clone(), 0, push_constants)
+                .unwrap();
+        }
+
+        builder
+            .draw(3, 1, 0, 0)
+            .unwrap()
+            .end_render_pass(Default::default())
+            .unwrap();
+    }
+}
+
+/// Single color attachment, either overwritten or blended onto (`load`)
+fn color_pass(vk: Arc<Vk>, format: Format, load: bool) -> Arc<RenderPass> {
+    match load {
+        true => vulkano::single_pass_renderpass!(vk.device.clone(),
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: Load,
+                    store_op: Store,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        ),
+        false => vulkano::single_pass_renderpass!(vk.device.clone(),
+            attachments: {
+                color: {
+                    format: format,
+                    samples: 1,
+                    load_op: DontCare,
+                    store_op: Store,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        ),
+    }
+    .unwrap()
+}
+
+fn framebuffer(render_pass: Arc<RenderPass>, attachments: Vec<Arc<ImageView>>) -> Arc<Framebuffer> {
+    Framebuffer::new(
+        render_pass,
+        FramebufferCreateInfo {
+            attachments,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn texel_size(extent: [u32; 2]) -> [f32; 2] {
+    [1.0 / extent[0] as f32, 1.0 / extent[1] as f32]
+}
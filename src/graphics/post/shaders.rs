@@ -0,0 +1,218 @@
+/// Fullscreen triangle shared by every effect, custom effects get `uv` at location 0
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) out vec2 uv;
+
+            void main() {
+                // single triangle covering the screen
+                uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+/// Keeps what is brighter than the threshold, with a soft knee
+pub mod threshold_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D u_input;
+
+            layout(push_constant) uniform ThresholdPC {
+                float threshold;
+                float knee;
+            };
+
+            layout(location = 0) in vec2 uv;
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                vec3 color = texture(u_input, uv).rgb;
+                float brightness = max(color.r, max(color.g, color.b));
+
+                float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+                soft = soft * soft / (4.0 * knee + 1e-4);
+
+                float contribution = max(soft, brightness - threshold) / max(brightness, 1e-4);
+                f_color = vec4(color * contribution, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod downsample_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D u_input;
+
+            layout(push_constant) uniform DownsamplePC {
+                vec2 texel_size; // of the input
+            };
+
+            layout(location = 0) in vec2 uv;
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                // 4 bilinear taps, 16 texels
+                vec4 o = texel_size.xyxy * vec4(-1.0, -1.0, 1.0, 1.0);
+
+                vec3 color = texture(u_input, uv + o.xy).rgb
+                    + texture(u_input, uv + o.zy).rgb
+                    + texture(u_input, uv + o.xw).rgb
+                    + texture(u_input, uv + o.zw).rgb;
+
+                f_color = vec4(color * 0.25, 1.0);
+            }
+        ",
+    }
+}
+
+/// Drawn with additive blending into the next bigger level
+pub mod upsample_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D u_input;
+
+            layout(push_constant) uniform UpsamplePC {
+                vec2 texel_size; // of the input
+                float radius;
+            };
+
+            layout(location = 0) in vec2 uv;
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                // 3x3 tent filter
+                vec4 o = texel_size.xyxy * vec4(1.0, 1.0, -1.0, 0.0) * radius;
+
+                vec3 color = texture(u_input, uv - o.xy).rgb
+                    + texture(u_input, uv - o.wy).rgb * 2.0
+                    + texture(u_input, uv - o.zy).rgb
+                    + texture(u_input, uv + o.zw).rgb * 2.0
+                    + texture(u_input, uv).rgb * 4.0
+                    + texture(u_input, uv + o.xw).rgb * 2.0
+                    + texture(u_input, uv + o.zy).rgb
+                    + texture(u_input, uv + o.wy).rgb * 2.0
+                    + texture(u_input, uv + o.xy).rgb;
+
+                f_color = vec4(color / 16.0, 1.0);
+            }
+        ",
+    }
+}
+
+/// Adds the bloom and maps the HDR scene to the output range
+pub mod tonemap_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D u_scene;
+            layout(set = 0, binding = 1) uniform sampler2D u_bloom;
+
+            layout(push_constant) uniform TonemapPC {
+                float exposure;
+                float bloom_intensity;
+                uint tonemap; // 0 clamps
+            };
+
+            layout(location = 0) in vec2 uv;
+            layout(location = 0) out vec4 f_color;
+
+            // Narkowicz's fit of the ACES filmic curve
+            vec3 aces(vec3 x) {
+                const float a = 2.51;
+                const float b = 0.03;
+                const float c = 2.43;
+                const float d = 0.59;
+                const float e = 0.14;
+                return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+            }
+
+            void main() {
+                vec3 color = texture(u_scene, uv).rgb + texture(u_bloom, uv).rgb * bloom_intensity;
+
+                if (tonemap != 0) {
+                    color = aces(color * exposure);
+                } else {
+                    color = clamp(color, 0.0, 1.0);
+                }
+
+                f_color = vec4(color, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod fxaa_fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D u_input;
+
+            layout(push_constant) uniform FxaaPC {
+                vec2 texel_size;
+            };
+
+            layout(location = 0) in vec2 uv;
+            layout(location = 0) out vec4 f_color;
+
+            const float REDUCE_MIN = 1.0 / 128.0;
+            const float REDUCE_MUL = 1.0 / 8.0;
+            const float SPAN_MAX = 8.0;
+
+            // the input is linear, sqrt is close enough to perceptual
+            float luma(vec3 color) {
+                return dot(sqrt(color), vec3(0.299, 0.587, 0.114));
+            }
+
+            void main() {
+                float luma_nw = luma(texture(u_input, uv + vec2(-1.0, -1.0) * texel_size).rgb);
+                float luma_ne = luma(texture(u_input, uv + vec2(1.0, -1.0) * texel_size).rgb);
+                float luma_sw = luma(texture(u_input, uv + vec2(-1.0, 1.0) * texel_size).rgb);
+                float luma_se = luma(texture(u_input, uv + vec2(1.0, 1.0) * texel_size).rgb);
+                float luma_m = luma(texture(u_input, uv).rgb);
+
+                float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+                float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+                vec2 dir = vec2(
+                    -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                    (luma_nw + luma_sw) - (luma_ne + luma_se)
+                );
+
+                float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+                float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+                dir = clamp(dir * rcp_dir_min, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel_size;
+
+                vec3 rgb_a = 0.5 * (
+                    texture(u_input, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+                    texture(u_input, uv + dir * (2.0 / 3.0 - 0.5)).rgb
+                );
+                vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+                    texture(u_input, uv - dir * 0.5).rgb +
+                    texture(u_input, uv + dir * 0.5).rgb
+                );
+
+                float luma_b = luma(rgb_b);
+                f_color = vec4((luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b, 1.0);
+            }
+        ",
+    }
+}
@@ -75,6 +75,8 @@ type Fence = Arc<FenceSignalFuture<PresentFuture<CommandBufferExecFuture<JoinFut
                 rp.clone(),
                 &self.images,
             );
+            /* after the old framebuffers, which held the views of the old swapchain images, are dropped */
+            vk.image_views.purge();
             
             if self.window_resized {    
                 self.window_resized = false;
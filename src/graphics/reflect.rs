@@ -0,0 +1,464 @@
+use std::{collections::{BTreeMap, HashMap}, fmt, sync::{Arc, Mutex, Weak}};
+
+use vulkano::{buffer::{BufferContents, Subbuffer}, descriptor_set::{layout::DescriptorType, PersistentDescriptorSet, WriteDescriptorSet}, image::{sampler::Sampler, view::ImageView}, pipeline::{Pipeline, PipelineLayout}, shader::spirv::{Decoration, Id, Instruction, Spirv, SpirvError, StorageClass}, Validated, VulkanError};
+
+use super::{command::BuilderType, hot_reload::{compile_source, ShaderError, ShaderKind}, vk::Vk};
+
+/// A descriptor of a shader, as declared in its SPIR-V
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    /// Array length, 0 for runtime sized arrays
+    pub count: u32,
+    /// Size of the block for uniform and storage buffers, not counting a trailing runtime array
+    pub block_size: Option<u64>,
+}
+
+/// Maps the names found in SPIR-V to descriptors.
+///
+/// Both the variable and the block name of a buffer are registered, so
+/// `layout(set = 0, binding = 0) uniform Camera { ... } camera;` is found as `"Camera"` and `"camera"`.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    bindings: HashMap<String, ReflectedBinding>,
+}
+
+impl ShaderReflection {
+    /// Merges the descriptors of every stage of a pipeline
+    pub fn new<'a>(stages: impl IntoIterator<Item = &'a [u32]>) -> Result<Self, SpirvError> {
+        let mut reflection = Self::default();
+
+        for words in stages {
+            reflection.add_stage(words)?;
+        }
+
+        Ok(reflection)
+    }
+
+    /// Compiles the GLSL of every stage with shaderc and reflects it.
+    ///
+    /// The SPIR-V of shaders built with `vulkano_shaders::shader!` can't be read back, the built-in shaders
+    /// are loaded with `path` and export the same file as `SOURCE` for this.
+    /// Includes are resolved like `hot_reload::compile_source`.
+    pub fn from_glsl<'a>(stages: impl IntoIterator<Item = (&'a str, ShaderKind)>) -> Result<Self, ShaderError> {
+        let mut reflection = Self::default();
+
+        for (source, kind) in stages {
+            let compiled = compile_source(source, kind, "reflected.glsl")?;
+            reflection.add_stage(&compiled.words)
+                .map_err(|e| ShaderError::Compile(e.to_string()))?;
+        }
+
+        Ok(reflection)
+    }
+
+    pub fn add_stage(&mut self, words: &[u32]) -> Result<(), SpirvError> {
+        let spirv = Spirv::new(words)?;
+
+        for instruction in spirv.iter_global() {
+            let &Instruction::Variable { result_type_id, result_id, storage_class, .. } = instruction else {
+                continue;
+            };
+
+            if !matches!(storage_class, StorageClass::Uniform | StorageClass::UniformConstant | StorageClass::StorageBuffer) {
+                continue;
+            }
+
+            let mut set = None;
+            let mut binding = None;
+            for decoration in spirv.id(result_id).iter_decoration() {
+                match decoration {
+                    Instruction::Decorate { decoration: Decoration::DescriptorSet { descriptor_set }, .. } => set = Some(*descriptor_set),
+                    Instruction::Decorate { decoration: Decoration::Binding { binding_point }, .. } => binding = Some(*binding_point),
+                    _ => {}
+                }
+            }
+            let (Some(set), Some(binding)) = (set, binding) else {
+                continue;
+            };
+
+            let &Instruction::TypePointer { ty, .. } = spirv.id(result_type_id).instruction() else {
+                continue;
+            };
+
+            /* arrays of descriptors */
+            let (ty, count) = match *spirv.id(ty).instruction() {
+                Instruction::TypeArray { element_type, length, .. } => (element_type, constant_u32(&spirv, length)),
+                Instruction::TypeRuntimeArray { element_type, .. } => (element_type, 0),
+                _ => (ty, 1),
+            };
+
+            let block_size = matches!(spirv.id(ty).instruction(), Instruction::TypeStruct { .. })
+                .then(|| type_size(&spirv, ty, None));
+
+            let reflected = ReflectedBinding {
+                set,
+                binding,
+                count,
+                block_size,
+            };
+
+            for id in [result_id, ty] {
+                if let Some(name) = name_of(&spirv, id) {
+                    self.bindings.insert(name, reflected.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ReflectedBinding> {
+        self.bindings.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.bindings.keys().map(String::as_str)
+    }
+}
+
+/// Keyed by the address of the layout, the weak reference tells a reused address apart
+type RegisteredReflections = HashMap<usize, (Weak<PipelineLayout>, Arc<ShaderReflection>)>;
+
+/// Reflections of pipelines, found by their layout. Filled by `HotPipeline`, by `GraphicsPipelineBuilder::reflect`
+/// or by hand with `insert_glsl` for pipelines built from other `shader!` modules.
+pub struct ReflectionCache {
+    reflections: Mutex<RegisteredReflections>,
+    /// By source, so rebuilding a pipeline doesn't compile its shaders again
+    glsl: Mutex<HashMap<Vec<&'static str>, Arc<ShaderReflection>>>,
+}
+
+impl ReflectionCache {
+    pub fn new() -> Self {
+        Self {
+            reflections: Mutex::new(HashMap::new()),
+            glsl: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the reflection of the pipelines using `layout`
+    pub fn insert(&self, layout: &Arc<PipelineLayout>, reflection: impl Into<Arc<ShaderReflection>>) {
+        let mut reflections = self.reflections.lock().unwrap();
+        reflections.retain(|_, (layout, _)| layout.strong_count() > 0);
+        reflections.insert(Arc::as_ptr(layout) as usize, (Arc::downgrade(layout), reflection.into()));
+    }
+
+    /// Reflects the GLSL the pipelines using `layout` were built from (see `ShaderReflection::from_glsl`) and registers it
+    pub fn insert_glsl(&self, layout: &Arc<PipelineLayout>, stages: &[(&'static str, ShaderKind)]) -> Result<(), ShaderError> {
+        let key = stages.iter().map(|(source, _)| *source).collect::<Vec<_>>();
+
+        let cached = self.glsl.lock().unwrap().get(&key).cloned();
+        let reflection = match cached {
+            Some(reflection) => reflection,
+            None => {
+                let reflection = Arc::new(ShaderReflection::from_glsl(stages.iter().copied())?);
+                self.glsl.lock().unwrap().insert(key, reflection.clone());
+                reflection
+            }
+        };
+
+        self.insert(layout, reflection);
+        Ok(())
+    }
+
+    pub fn get(&self, layout: &Arc<PipelineLayout>) -> Option<Arc<ShaderReflection>> {
+        self.reflections.lock().unwrap()
+            .get(&(Arc::as_ptr(layout) as usize))
+            .filter(|(registered, _)| registered.upgrade().is_some_and(|registered| Arc::ptr_eq(&registered, layout)))
+            .map(|(_, reflection)| reflection.clone())
+    }
+}
+
+impl Default for ReflectionCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn name_of(spirv: &Spirv, id: Id) -> Option<String> {
+    spirv.id(id).iter_name().find_map(|name| match name {
+        Instruction::Name { name, .. } if !name.is_empty() => Some(name.clone()),
+        _ => None,
+    })
+}
+
+fn constant_u32(spirv: &Spirv, id: Id) -> u32 {
+    match spirv.id(id).instruction() {
+        Instruction::Constant { value, .. } => value[0],
+        _ => 1,
+    }
+}
+
+/// Size in bytes following the explicit layout decorations,
+/// `matrix_stride` comes from the decoration of the struct member holding the matrix
+fn type_size(spirv: &Spirv, ty: Id, matrix_stride: Option<u32>) -> u64 {
+    let info = spirv.id(ty);
+
+    match *info.instruction() {
+        Instruction::TypeBool { .. } => 4,
+        Instruction::TypeInt { width, .. } | Instruction::TypeFloat { width, .. } => width as u64 / 8,
+        Instruction::TypeVector { component_type, component_count, .. } => {
+            component_count as u64 * type_size(spirv, component_type, None)
+        }
+        Instruction::TypeMatrix { column_type, column_count, .. } => {
+            let stride = matrix_stride
+                .map(u64::from)
+                .unwrap_or_else(|| type_size(spirv, column_type, None));
+            column_count as u64 * stride
+        }
+        Instruction::TypeArray { element_type, length, .. } => {
+            let stride = info.iter_decoration()
+                .find_map(|decoration| match decoration {
+                    Instruction::Decorate { decoration: Decoration::ArrayStride { array_stride }, .. } => Some(*array_stride as u64),
+                    _ => None,
+                })
+                .unwrap_or_else(|| type_size(spirv, element_type, matrix_stride));
+            constant_u32(spirv, length) as u64 * stride
+        }
+        Instruction::TypeRuntimeArray { .. } => 0,
+        Instruction::TypeStruct { ref member_types, .. } => {
+            member_types
+                .iter()
+                .zip(info.iter_members())
+                .map(|(&member_type, member)| {
+                    let mut offset = 0;
+                    let mut stride = None;
+                    for decoration in member.iter_decoration() {
+                        match decoration {
+                            Instruction::MemberDecorate { decoration: Decoration::Offset { byte_offset }, .. } => offset = *byte_offset as u64,
+                            Instruction::MemberDecorate { decoration: Decoration::MatrixStride { matrix_stride }, .. } => stride = Some(*matrix_stride),
+                            _ => {}
+                        }
+                    }
+
+                    offset + type_size(spirv, member_type, stride)
+                })
+                .max()
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+#[derive(Debug)]
+pub enum BindError {
+    /// Nothing is registered in `Vk::reflections` for the pipeline, see `GraphicsPipelineBuilder::reflect`
+    NoReflection,
+    UnknownName(String),
+    /// The resource does not fit the descriptor type declared by the shader
+    TypeMismatch {
+        name: String,
+        expected: DescriptorType,
+        given: &'static str,
+    },
+    BufferTooSmall {
+        name: String,
+        required: u64,
+        size: u64,
+    },
+    TooManyElements {
+        name: String,
+        count: u32,
+        given: usize,
+    },
+    /// The pipeline layout has no descriptor at the reflected set and binding
+    NotInLayout(String),
+    /// The per-instance binding 1 of the pipeline does not have the stride of the instances of the mesh
+    InstanceStride {
+        instances: &'static str,
+        stride: u32,
+    },
+    Vulkan(Validated<VulkanError>),
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindError::NoReflection => write!(f, "the pipeline has no reflection in `Vk::reflections`"),
+            BindError::UnknownName(name) => write!(f, "no descriptor named `{name}` in the shaders"),
+            BindError::TypeMismatch { name, expected, given } => {
+                write!(f, "`{name}` is a {expected:?}, but a {given} was given")
+            }
+            BindError::BufferTooSmall { name, required, size } => {
+                write!(f, "`{name}` needs at least {required} bytes, the buffer has {size}")
+            }
+            BindError::TooManyElements { name, count, given } => {
+                write!(f, "`{name}` holds {count} descriptors, {given} were given")
+            }
+            BindError::NotInLayout(name) => write!(f, "`{name}` is not part of the pipeline layout"),
+            BindError::InstanceStride { instances, stride } => write!(
+                f,
+                "the mesh has {instances} instances but the pipeline takes {stride} bytes per instance at binding 1, \
+                 draw meshes with `set_instance_transforms` with a `ModelInstanceData` pipeline and the others with a `PosInstanceData` one",
+            ),
+            BindError::Vulkan(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+impl From<Validated<VulkanError>> for BindError {
+    fn from(e: Validated<VulkanError>) -> Self {
+        BindError::Vulkan(e)
+    }
+}
+
+/// Collects descriptor writes by name and builds one set per descriptor set index.
+///
+/// ```ignore
+/// let sets = DescriptorWriter::new(pipeline.clone(), &reflection)
+///     .buffer("Camera", camera_ubo.content.clone())?
+///     .image_view_sampler("tex", view, sampler)?
+///     .build(vk.clone())?;
+/// ```
+pub struct DescriptorWriter<'a, P: Pipeline> {
+    pipeline: Arc<P>,
+    reflection: &'a ShaderReflection,
+    writes: BTreeMap<u32, Vec<WriteDescriptorSet>>,
+}
+
+impl<'a, P: Pipeline> DescriptorWriter<'a, P> {
+    pub fn new(pipeline: Arc<P>, reflection: &'a ShaderReflection) -> Self {
+        Self {
+            pipeline,
+            reflection,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Finds `name` and checks that the layout declares one of `types` for it
+    fn lookup(&self, name: &str, types: &[DescriptorType], given: &'static str) -> Result<&'a ReflectedBinding, BindError> {
+        let reflected = self.reflection
+            .get(name)
+            .ok_or_else(|| BindError::UnknownName(name.to_string()))?;
+
+        let descriptor_type = self.pipeline.layout()
+            .set_layouts()
+            .get(reflected.set as usize)
+            .and_then(|layout| layout.bindings().get(&reflected.binding))
+            .ok_or_else(|| BindError::NotInLayout(name.to_string()))?
+            .descriptor_type;
+
+        if !types.contains(&descriptor_type) {
+            return Err(BindError::TypeMismatch {
+                name: name.to_string(),
+                expected: descriptor_type,
+                given,
+            });
+        }
+
+        Ok(reflected)
+    }
+
+    fn check_len(name: &str, reflected: &ReflectedBinding, given: usize) -> Result<(), BindError> {
+        if reflected.count != 0 && given > reflected.count as usize {
+            return Err(BindError::TooManyElements {
+                name: name.to_string(),
+                count: reflected.count,
+                given,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, set: u32, write: WriteDescriptorSet) {
+        self.writes.entry(set).or_default().push(write);
+    }
+
+    /// Adds a write found without reflection, e.g. `Material::writes`
+    pub fn write(mut self, set: u32, write: WriteDescriptorSet) -> Self {
+        self.push(set, write);
+        self
+    }
+
+    /// Uniform or storage buffer, at least as big as the block declared by the shader
+    pub fn buffer<T: BufferContents + ?Sized>(mut self, name: &str, buffer: Subbuffer<T>) -> Result<Self, BindError> {
+        let reflected = self.lookup(
+            name,
+            &[DescriptorType::UniformBuffer, DescriptorType::StorageBuffer, DescriptorType::UniformBufferDynamic, DescriptorType::StorageBufferDynamic],
+            "buffer",
+        )?;
+
+        let required = reflected.block_size.unwrap_or(0);
+        if buffer.size() < required {
+            return Err(BindError::BufferTooSmall {
+                name: name.to_string(),
+                required,
+                size: buffer.size(),
+            });
+        }
+
+        self.push(reflected.set, WriteDescriptorSet::buffer(reflected.binding, buffer));
+        Ok(self)
+    }
+
+    /// `sampler2D` and friends
+    pub fn image_view_sampler(self, name: &str, view: Arc<ImageView>, sampler: Arc<Sampler>) -> Result<Self, BindError> {
+        self.image_view_sampler_array(name, 0, [(view, sampler)])
+    }
+
+    pub fn image_view_sampler_array(
+        mut self,
+        name: &str,
+        first_element: u32,
+        elements: impl IntoIterator<Item = (Arc<ImageView>, Arc<Sampler>)>,
+    ) -> Result<Self, BindError> {
+        let reflected = self.lookup(name, &[DescriptorType::CombinedImageSampler], "combined image sampler")?;
+        let elements = elements.into_iter().collect::<Vec<_>>();
+        Self::check_len(name, reflected, first_element as usize + elements.len())?;
+
+        self.push(reflected.set, WriteDescriptorSet::image_view_sampler_array(reflected.binding, first_element, elements));
+        Ok(self)
+    }
+
+    /// `texture2D`, `image2D` or `subpassInput`
+    pub fn image_view(mut self, name: &str, view: Arc<ImageView>) -> Result<Self, BindError> {
+        let reflected = self.lookup(
+            name,
+            &[DescriptorType::SampledImage, DescriptorType::StorageImage, DescriptorType::InputAttachment],
+            "image view",
+        )?;
+
+        self.push(reflected.set, WriteDescriptorSet::image_view(reflected.binding, view));
+        Ok(self)
+    }
+
+    pub fn sampler(mut self, name: &str, sampler: Arc<Sampler>) -> Result<Self, BindError> {
+        let reflected = self.lookup(name, &[DescriptorType::Sampler], "sampler")?;
+
+        self.push(reflected.set, WriteDescriptorSet::sampler(reflected.binding, sampler));
+        Ok(self)
+    }
+
+    /// One descriptor set per set index that received writes, sorted by index
+    pub fn build(self, vk: Arc<Vk>) -> Result<Vec<(u32, Arc<PersistentDescriptorSet>)>, BindError> {
+        let set_layouts = self.pipeline.layout().set_layouts();
+
+        self.writes
+            .into_iter()
+            .map(|(set, writes)| {
+                let layout = set_layouts[set as usize].clone();
+                let descriptor_set = PersistentDescriptorSet::new(&vk.allocators.descriptor_set, layout, writes, [])?;
+                Ok((set, descriptor_set))
+            })
+            .collect()
+    }
+
+    /// Builds the sets and binds them to the pipeline's bind point.
+    ///
+    /// Warning: this function assumes the pipeline has already been bounded
+    pub fn bind(self, vk: Arc<Vk>, builder: &mut BuilderType) -> Result<(), BindError> {
+        let pipeline = self.pipeline.clone();
+
+        for (set, descriptor_set) in self.build(vk)? {
+            builder
+                .bind_descriptor_sets(pipeline.bind_point(), pipeline.layout().clone(), set, descriptor_set)
+                .map_err(|e| BindError::Vulkan(Validated::ValidationError(e)))?;
+        }
+
+        Ok(())
+    }
+}
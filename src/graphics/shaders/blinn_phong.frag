@@ -0,0 +1,4 @@
+#version 460
+
+#define SHADE blinn_phong
+#include "lit.frag"
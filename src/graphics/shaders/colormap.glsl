@@ -0,0 +1,54 @@
+// Colormaps for scalar fields, `t` in [0, 1]. Ids match `terrain::Colormap`
+
+#define COLORMAP_GRAYSCALE 0
+#define COLORMAP_VIRIDIS 1
+#define COLORMAP_COOLWARM 2
+#define COLORMAP_TERRAIN 3
+
+// polynomial fit of matplotlib's viridis
+vec3 viridis(float t) {
+    const vec3 c0 = vec3(0.2777273272234177, 0.005407344544966578, 0.3340998053353061);
+    const vec3 c1 = vec3(0.1050930431085774, 1.404613529898575, 1.384590162594685);
+    const vec3 c2 = vec3(-0.3308618287255563, 0.214847559468213, 0.09509516302823659);
+    const vec3 c3 = vec3(-4.634230498983486, -5.799100973351585, -19.33244095627987);
+    const vec3 c4 = vec3(6.228269936347081, 14.17993336680509, 56.69055260068105);
+    const vec3 c5 = vec3(4.776384997670288, -13.74514537774601, -65.35303263337234);
+    const vec3 c6 = vec3(-5.435455855934631, 4.645852612178535, 26.3124352495832);
+
+    return c0 + t * (c1 + t * (c2 + t * (c3 + t * (c4 + t * (c5 + t * c6)))));
+}
+
+// diverging blue, white, red
+vec3 coolwarm(float t) {
+    const vec3 cool = vec3(0.23, 0.30, 0.75);
+    const vec3 white = vec3(0.87, 0.87, 0.87);
+    const vec3 warm = vec3(0.71, 0.02, 0.15);
+
+    return t < 0.5 ? mix(cool, white, t * 2.0) : mix(white, warm, t * 2.0 - 1.0);
+}
+
+// water, sand, grass, rock, snow
+vec3 terrain(float t) {
+    const vec3 stops[5] = vec3[](
+        vec3(0.10, 0.25, 0.60),
+        vec3(0.85, 0.80, 0.55),
+        vec3(0.25, 0.55, 0.20),
+        vec3(0.45, 0.35, 0.25),
+        vec3(0.95, 0.95, 0.95)
+    );
+
+    float x = t * 4.0;
+    int i = min(int(x), 3);
+    return mix(stops[i], stops[i + 1], x - float(i));
+}
+
+vec3 colormap(uint map, float t) {
+    t = clamp(t, 0.0, 1.0);
+
+    switch (map) {
+        case COLORMAP_VIRIDIS: return viridis(t);
+        case COLORMAP_COOLWARM: return coolwarm(t);
+        case COLORMAP_TERRAIN: return terrain(t);
+        default: return vec3(t);
+    }
+}
@@ -0,0 +1,15 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+
+layout(location = 1) in vec3 ofs; // per instance
+
+layout(push_constant) uniform ShadowPC {
+    mat4 light_matrix;
+};
+
+#include "model.glsl"
+
+void main() {
+    gl_Position = light_matrix * model * vec4(pos + ofs, 1.0);
+}
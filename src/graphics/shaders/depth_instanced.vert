@@ -0,0 +1,14 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+#include "instance.glsl"
+
+layout(push_constant) uniform ShadowPC {
+    mat4 light_matrix;
+};
+
+#include "model.glsl"
+
+void main() {
+    gl_Position = light_matrix * model * instance_model * vec4(pos, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 460
+
+layout(push_constant) uniform GeometryPC {
+    vec4 albedo;
+    uint material;
+};
+
+layout(location = 0) in vec3 world_pos;
+layout(location = 1) in float view_depth;
+
+layout(location = 0) out vec4 f_albedo;
+layout(location = 1) out vec4 f_normal;
+layout(location = 2) out vec4 f_position;
+layout(location = 3) out uint f_material;
+
+void main() {
+    // meshes have no normals, use the face normal. its sign is fixed in the lighting pass
+    vec3 normal = normalize(cross(dFdx(world_pos), dFdy(world_pos)));
+
+    f_albedo = vec4(albedo.rgb, 1.0);
+    f_normal = vec4(normal, 0.0);
+    f_position = vec4(world_pos, view_depth);
+    f_material = material;
+}
@@ -0,0 +1,23 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+#include "instance.glsl"
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+#include "model.glsl"
+
+layout(location = 0) out vec3 world_pos;
+layout(location = 1) out float view_depth;
+
+void main() {
+    vec4 world = model * instance_model * vec4(pos, 1.0);
+    vec4 view_pos = view * world;
+
+    world_pos = world.xyz;
+    view_depth = abs(view_pos.z);
+    gl_Position = proj * view_pos;
+}
@@ -0,0 +1,5 @@
+// Per instance `ModelInstanceData`, at binding 1 in place of `PosInstanceData`
+
+layout(location = 1) in mat4 instance_model; // locations 1 to 4
+layout(location = 5) in vec4 instance_color;
+layout(location = 6) in uint instance_custom;
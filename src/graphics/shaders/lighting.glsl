@@ -0,0 +1,96 @@
+// Lights uploaded by `graphics::lighting::Lights` and the two shading models of the lit shaders.
+
+#ifndef CHAOS_LIGHTING_GLSL
+#define CHAOS_LIGHTING_GLSL
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+const float PI = 3.14159265359;
+
+struct Light {
+    vec4 position_range;  // xyz: position, w: distance where the light reaches 0
+    vec4 direction_type;  // xyz: direction the light travels in, w: LIGHT_*
+    vec4 color_intensity;
+    vec4 cone;            // x: cos of the inner angle, y: cos of the outer angle (spot)
+};
+
+// Direction from the surface to the light, and the radiance reaching the surface
+vec3 light_radiance(Light light, vec3 world_pos, out vec3 to_light) {
+    uint type = uint(light.direction_type.w);
+    vec3 radiance = light.color_intensity.rgb * light.color_intensity.w;
+
+    if (type == LIGHT_DIRECTIONAL) {
+        to_light = -normalize(light.direction_type.xyz);
+        return radiance;
+    }
+
+    vec3 offset = light.position_range.xyz - world_pos;
+    float dist = length(offset);
+    to_light = offset / max(dist, 1e-4);
+
+    // inverse square, windowed to reach 0 at the range
+    float window = clamp(1.0 - pow(dist / light.position_range.w, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (dist * dist + 1.0);
+
+    if (type == LIGHT_SPOT) {
+        float cos_angle = dot(-to_light, normalize(light.direction_type.xyz));
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+
+    return radiance * attenuation;
+}
+
+vec3 blinn_phong(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, float metallic, float roughness) {
+    vec3 h = normalize(l + v);
+
+    float a = max(roughness * roughness, 1e-3);
+    float shininess = max(2.0 / (a * a) - 2.0, 1.0);
+
+    vec3 specular_color = mix(vec3(0.04), albedo, metallic);
+    vec3 diffuse = albedo * (1.0 - metallic);
+
+    // normalized so rough and smooth surfaces reflect the same energy
+    float specular = pow(max(dot(n, h), 0.0), shininess) * (shininess + 8.0) / (8.0 * PI);
+
+    return (diffuse / PI + specular_color * specular) * radiance * max(dot(n, l), 0.0);
+}
+
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / max(PI * d * d, 1e-7);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float gv = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float gl = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return gv * gl;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Cook-Torrance with GGX, metallic-roughness workflow
+vec3 pbr(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, float metallic, float roughness) {
+    vec3 h = normalize(l + v);
+    float n_dot_l = max(dot(n, l), 0.0);
+    float n_dot_v = max(dot(n, v), 1e-4);
+    float n_dot_h = max(dot(n, h), 0.0);
+
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+    float d = distribution_ggx(n_dot_h, roughness);
+    float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+    vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 1e-4);
+    vec3 diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+#endif
@@ -0,0 +1,59 @@
+// Fragment shader of `graphics::lighting`, `SHADE` is `blinn_phong` or `pbr`
+
+#include "lighting.glsl"
+
+#include "model.glsl"
+
+layout(set = 1, binding = 1) uniform Material {
+    vec4 base_color;
+    vec4 emissive;
+    float metallic;
+    float roughness;
+} material;
+
+layout(set = 1, binding = 2) uniform sampler2D base_color_texture;
+layout(set = 1, binding = 3) uniform sampler2D metallic_roughness_texture;
+layout(set = 1, binding = 4) uniform sampler2D emissive_texture;
+
+layout(set = 2, binding = 0) readonly buffer Lights {
+    Light lights[];
+};
+
+layout(set = 2, binding = 1) uniform LightInfo {
+    vec4 ambient;
+    uint light_count;
+};
+
+layout(location = 0) in vec3 v_world_pos;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_uv;
+layout(location = 3) in vec3 v_camera_pos;
+layout(location = 4) in vec4 v_instance_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec4 base = material.base_color * vec4(color.rgb, 1.0) * v_instance_color * texture(base_color_texture, v_uv);
+
+    vec4 metallic_roughness = texture(metallic_roughness_texture, v_uv);
+    float metallic = clamp(material.metallic * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+
+    vec3 v = normalize(v_camera_pos - v_world_pos);
+    vec3 n = normalize(v_normal);
+    // two sided
+    if (dot(n, v) < 0.0) {
+        n = -n;
+    }
+
+    vec3 result = ambient.rgb * base.rgb;
+    for (uint i = 0; i < light_count; i++) {
+        vec3 l;
+        vec3 radiance = light_radiance(lights[i], v_world_pos, l);
+        result += SHADE(n, v, l, radiance, base.rgb, metallic, roughness);
+    }
+
+    result += material.emissive.rgb * texture(emissive_texture, v_uv).rgb;
+
+    f_color = vec4(result, base.a);
+}
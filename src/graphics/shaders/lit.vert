@@ -0,0 +1,31 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+layout(location = 1) in vec3 ofs; // per instance
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 uv;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+#include "model.glsl"
+
+layout(location = 0) out vec3 v_world_pos;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_uv;
+layout(location = 3) out vec3 v_camera_pos;
+layout(location = 4) out vec4 v_instance_color;
+
+void main() {
+    vec4 world = model * vec4(pos + ofs, 1.0);
+
+    v_world_pos = world.xyz;
+    v_normal = transpose(inverse(mat3(model))) * normal;
+    v_uv = uv;
+    v_camera_pos = inverse(view)[3].xyz;
+    v_instance_color = vec4(1.0);
+
+    gl_Position = proj * view * world;
+}
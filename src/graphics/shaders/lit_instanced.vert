@@ -0,0 +1,32 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+#include "instance.glsl"
+layout(location = 7) in vec3 normal;
+layout(location = 8) in vec2 uv;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+#include "model.glsl"
+
+layout(location = 0) out vec3 v_world_pos;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_uv;
+layout(location = 3) out vec3 v_camera_pos;
+layout(location = 4) out vec4 v_instance_color;
+
+void main() {
+    mat4 transform = model * instance_model;
+    vec4 world = transform * vec4(pos, 1.0);
+
+    v_world_pos = world.xyz;
+    v_normal = transpose(inverse(mat3(transform))) * normal;
+    v_uv = uv;
+    v_camera_pos = inverse(view)[3].xyz;
+    v_instance_color = instance_color;
+
+    gl_Position = proj * view * world;
+}
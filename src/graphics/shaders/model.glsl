@@ -0,0 +1,11 @@
+// Model block of the `Mesh` shaders, bound by name by `Mesh::bind_model`
+
+#ifndef CHAOS_MODEL_GLSL
+#define CHAOS_MODEL_GLSL
+
+layout(set = 1, binding = 0) uniform Model {
+    mat4 model;
+    vec4 color;
+};
+
+#endif
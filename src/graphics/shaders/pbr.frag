@@ -0,0 +1,4 @@
+#version 460
+
+#define SHADE pbr
+#include "lit.frag"
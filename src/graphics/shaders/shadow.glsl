@@ -0,0 +1,75 @@
+// Sampling of the shadow maps rendered by `graphics::shadow`.
+//
+// Bind `ShadowMap::view` with `ShadowMap::sampler` as a `sampler2DArrayShadow`,
+// and `CascadedShadows::data()` or `SpotShadow::data()` inside a uniform block.
+
+#ifndef CHAOS_SHADOW_GLSL
+#define CHAOS_SHADOW_GLSL
+
+#define MAX_CASCADES 4
+
+struct DirectionalShadowData {
+    mat4 matrices[MAX_CASCADES];
+    vec4 splits;      // view depth where each cascade ends
+    vec4 texel_sizes; // world size of a shadow map texel, per cascade
+    vec4 direction;   // xyz: direction the light travels in
+    vec4 params;      // x: cascade count, y: depth bias, z: normal offset in texels, w: pcf radius in texels
+};
+
+struct SpotShadowData {
+    mat4 matrix;
+    vec4 params;      // y: depth bias, w: pcf radius in texels
+};
+
+// (2 * radius + 1)^2 comparisons around `light_pos`, 1.0 is fully lit
+float shadow_pcf(sampler2DArrayShadow map, float layer, vec4 light_pos, float bias, int radius) {
+    vec3 p = light_pos.xyz / light_pos.w;
+    if (p.z <= 0.0 || p.z >= 1.0) {
+        return 1.0;
+    }
+
+    vec2 uv = p.xy * 0.5 + 0.5;
+    vec2 texel = 1.0 / vec2(textureSize(map, 0).xy);
+
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += texture(map, vec4(uv + vec2(x, y) * texel, layer, p.z - bias));
+        }
+    }
+
+    float taps = float((2 * radius + 1) * (2 * radius + 1));
+    return lit / taps;
+}
+
+int shadow_cascade(DirectionalShadowData s, float view_depth) {
+    int count = int(s.params.x);
+    for (int i = 0; i < count - 1; i++) {
+        if (view_depth < s.splits[i]) {
+            return i;
+        }
+    }
+    return count - 1;
+}
+
+// `view_depth` is the distance along the camera direction, as used for the cascade splits
+float directional_shadow(sampler2DArrayShadow map, DirectionalShadowData s, vec3 world_pos, vec3 normal, float view_depth) {
+    int count = int(s.params.x);
+    if (count == 0 || view_depth > s.splits[count - 1]) {
+        return 1.0;
+    }
+
+    int cascade = shadow_cascade(s, view_depth);
+
+    // pushing the receiver along its normal hides acne on surfaces almost parallel to the light
+    vec3 offset = normal * s.texel_sizes[cascade] * s.params.z;
+    vec4 light_pos = s.matrices[cascade] * vec4(world_pos + offset, 1.0);
+
+    return shadow_pcf(map, float(cascade), light_pos, s.params.y, int(s.params.w));
+}
+
+float spot_shadow(sampler2DArrayShadow map, SpotShadowData s, vec3 world_pos) {
+    return shadow_pcf(map, 0.0, s.matrix * vec4(world_pos, 1.0), s.params.y, int(s.params.w));
+}
+
+#endif
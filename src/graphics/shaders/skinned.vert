@@ -0,0 +1,43 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+layout(location = 1) in vec3 ofs; // per instance
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in uvec4 joints;
+layout(location = 5) in vec4 weights;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+#include "model.glsl"
+
+layout(set = 3, binding = 0) readonly buffer Joints {
+    mat4 joint_matrices[];
+};
+
+layout(location = 0) out vec3 v_world_pos;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec2 v_uv;
+layout(location = 3) out vec3 v_camera_pos;
+layout(location = 4) out vec4 v_instance_color;
+
+void main() {
+    mat4 skin =
+        weights.x * joint_matrices[joints.x] +
+        weights.y * joint_matrices[joints.y] +
+        weights.z * joint_matrices[joints.z] +
+        weights.w * joint_matrices[joints.w];
+
+    vec4 world = model * (skin * vec4(pos, 1.0) + vec4(ofs, 0.0));
+
+    v_world_pos = world.xyz;
+    v_normal = transpose(inverse(mat3(model * skin))) * normal;
+    v_uv = uv;
+    v_camera_pos = inverse(view)[3].xyz;
+    v_instance_color = vec4(1.0);
+
+    gl_Position = proj * view * world;
+}
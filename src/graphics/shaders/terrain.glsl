@@ -0,0 +1,22 @@
+// Bindings of `graphics::terrain`, the camera is set 0
+
+layout(set = 1, binding = 0) readonly buffer Heights {
+    float heights[]; // row major, x first
+};
+
+// `TerrainInfo` in terrain/mod.rs
+layout(set = 1, binding = 1) uniform TerrainInfo {
+    mat4 model;
+    uvec2 size;
+    vec2 cell_size;
+    float height_scale;
+    float min_height;
+    float max_height;
+    uint colormap_id;
+    vec4 light; // direction the light travels in, ambient
+} terrain_info;
+
+float height_at(uvec2 g) {
+    g = min(g, terrain_info.size - 1u);
+    return heights[g.y * terrain_info.size.x + g.x];
+}
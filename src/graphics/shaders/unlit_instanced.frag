@@ -0,0 +1,10 @@
+#version 460
+
+layout(location = 0) in vec4 v_color;
+layout(location = 1) flat in uint v_custom;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    f_color = v_color;
+}
@@ -0,0 +1,21 @@
+#version 460
+
+layout(location = 0) in vec3 pos;
+#include "instance.glsl"
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 proj;
+};
+
+#include "model.glsl"
+
+layout(location = 0) out vec4 v_color;
+layout(location = 1) flat out uint v_custom;
+
+void main() {
+    v_color = vec4(color.rgb, 1.0) * instance_color;
+    v_custom = instance_custom;
+
+    gl_Position = proj * view * model * instance_model * vec4(pos, 1.0);
+}
@@ -0,0 +1,421 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3, Vec4};
+use vulkano::{buffer::BufferContents, command_buffer::{RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::WriteDescriptorSet, format::{Format, FormatFeatures}, image::{sampler::{Filter, Sampler}, view::{ImageView, ImageViewCreateInfo, ImageViewType}, Image, ImageCreateInfo, ImageSubresourceRange, ImageUsage}, memory::allocator::AllocationCreateInfo, pipeline::{graphics::viewport::Viewport, DynamicState, GraphicsPipeline, Pipeline}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass}, shader::ShaderModule};
+
+use super::{cache::SamplerDesc, camera::Camera, command::BuilderType, mesh::mesh::Mesh, pipeline::GraphicsPipelineBuilder, reflect::BindError, vertex::{ModelInstanceData, PosInstanceData, PosVertex}, vk::Vk};
+
+pub mod shaders;
+
+/// Must match `MAX_CASCADES` in `shaders/shadow.glsl`
+pub const MAX_CASCADES: usize = 4;
+
+/// `DirectionalShadowData` in `shaders/shadow.glsl`
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct DirectionalShadowData {
+    pub matrices: [[[f32; 4]; 4]; MAX_CASCADES],
+    pub splits: [f32; 4],
+    pub texel_sizes: [f32; 4],
+    pub direction: [f32; 4],
+    pub params: [f32; 4],
+}
+
+/// `SpotShadowData` in `shaders/shadow.glsl`
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct SpotShadowData {
+    pub matrix: [[f32; 4]; 4],
+    pub params: [f32; 4],
+}
+
+/// A depth only layered map, one layer per view of the light (one per cascade, one for a spot light),
+/// with the depth only pipeline `Mesh`es are rendered with
+pub struct ShadowMap {
+    pub image: Arc<Image>,
+    /// `Dim2dArray` view over every layer, a `sampler2DArrayShadow` in the shaders
+    pub view: Arc<ImageView>,
+    pub size: u32,
+    pub render_pass: Arc<RenderPass>,
+    pub pipeline: Arc<GraphicsPipeline>,
+    /// `pipeline` for meshes with `Mesh::set_instance_transforms`
+    pub instanced_pipeline: Arc<GraphicsPipeline>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+impl ShadowMap {
+    /// `size`x`size` texels per layer
+    pub fn new(vk: Arc<Vk>, size: u32, layers: u32) -> Self {
+        let format = shadow_format(vk.clone());
+
+        let image = Image::new(
+            vk.allocators.memory.clone(),
+            ImageCreateInfo {
+                format,
+                extent: [size, size, 1],
+                array_layers: layers,
+                usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+
+        let view = ImageView::new(
+            image.clone(),
+            ImageViewCreateInfo {
+                view_type: ImageViewType::Dim2dArray,
+                ..ImageViewCreateInfo::from_image(&image)
+            },
+        )
+        .unwrap();
+
+        let render_pass = vulkano::single_pass_renderpass!(vk.device.clone(),
+            attachments: {
+                depth: {
+                    format: format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                }
+            },
+            pass: {
+                color: [],
+                depth_stencil: {depth},
+            },
+        )
+        .unwrap();
+
+        let framebuffers = (0..layers)
+            .map(|layer| {
+                let layer_view = ImageView::new(
+                    image.clone(),
+                    ImageViewCreateInfo {
+                        view_type: ImageViewType::Dim2d,
+                        subresource_range: ImageSubresourceRange {
+                            array_layers: layer..layer + 1,
+                            ..image.subresource_range()
+                        },
+                        ..ImageViewCreateInfo::from_image(&image)
+                    },
+                )
+                .unwrap();
+
+                Framebuffer::new(
+                    render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![layer_view],
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+
+        /* depth only: no fragment shader, the bias keeps lit surfaces from shadowing themselves */
+        let pipeline = GraphicsPipelineBuilder::new(shaders::vs::load(vk.device.clone()).unwrap(), None::<Arc<ShaderModule>>)
+            .vertex::<PosVertex>()
+            .instance::<PosInstanceData>()
+            .depth_bias(1.25, 1.75)
+            .dynamic_state(DynamicState::Viewport)
+            .reflect(shaders::vs::SOURCE, None)
+            .build(vk.clone(), render_pass.clone());
+        let instanced_pipeline = GraphicsPipelineBuilder::new(shaders::instanced_vs::load(vk.device.clone()).unwrap(), None::<Arc<ShaderModule>>)
+            .vertex::<PosVertex>()
+            .instance::<ModelInstanceData>()
+            .depth_bias(1.25, 1.75)
+            .dynamic_state(DynamicState::Viewport)
+            .reflect(shaders::instanced_vs::SOURCE, None)
+            .build(vk, render_pass.clone());
+
+        Self {
+            image,
+            view,
+            size,
+            render_pass,
+            pipeline,
+            instanced_pipeline,
+            framebuffers,
+        }
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.framebuffers.len() as u32
+    }
+
+    /// Comparison sampler for `view`, linear (hardware 2x2 PCF) when the format allows it
+    pub fn sampler(&self, vk: Arc<Vk>) -> Arc<Sampler> {
+        let linear = vk.physical_device
+            .format_properties(self.image.format())
+            .unwrap()
+            .optimal_tiling_features
+            .intersects(FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR);
+        let filter = if linear { Filter::Linear } else { Filter::Nearest };
+
+        vk.samplers.get(SamplerDesc {
+            mag_filter: filter,
+            min_filter: filter,
+            ..SamplerDesc::shadow()
+        })
+    }
+
+    /// `view` and `sampler` at `binding`, for the main pass
+    pub fn write(&self, vk: Arc<Vk>, binding: u32) -> WriteDescriptorSet {
+        WriteDescriptorSet::image_view_sampler(binding, self.view.clone(), self.sampler(vk))
+    }
+
+    /// Renders `meshes` into `layer` as seen by `light_matrix` (projection * view), with `instanced_pipeline`
+    /// for the meshes with `Mesh::set_instance_transforms`. Must be recorded outside of any render pass.
+    pub fn build_commands<'a>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        layer: u32,
+        light_matrix: Mat4,
+        meshes: impl IntoIterator<Item = &'a Mesh>,
+    ) -> Result<(), BindError> {
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(1.0.into())],
+                    ..RenderPassBeginInfo::framebuffer(self.framebuffers[layer as usize].clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .set_viewport(0, [Viewport {
+                offset: [0.0, 0.0],
+                extent: [self.size as f32, self.size as f32],
+                depth_range: 0.0..=1.0,
+            }].into_iter().collect())
+            .unwrap();
+
+        /* a mesh that fails to bind is skipped, the first error is returned once the pass is closed */
+        let mut result = Ok(());
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for mesh in meshes {
+            let pipeline = if mesh.tbo.is_some() { &self.instanced_pipeline } else { &self.pipeline };
+
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                builder
+                    .bind_pipeline_graphics(pipeline.clone())
+                    .unwrap()
+                    .push_constants(pipeline.layout().clone(), 0, shaders::vs::ShadowPC {
+                        light_matrix: light_matrix.to_cols_array_2d(),
+                    })
+                    .unwrap();
+                bound = Some(pipeline);
+            }
+
+            result = result.and(mesh.build_commands(vk.clone(), builder, pipeline.clone()));
+        }
+
+        builder
+            .end_render_pass(Default::default())
+            .unwrap();
+
+        result
+    }
+}
+
+/// Cascaded shadow maps for a directional light: the camera frustum is split by distance
+/// and every slice gets its own layer, so near shadows stay sharp over large scenes
+pub struct CascadedShadows {
+    pub map: ShadowMap,
+    pub cascades: usize,
+    /// Direction the light travels in
+    pub direction: Vec3,
+    /// Shadows end at this view distance, or at the camera far plane if closer
+    pub max_distance: f32,
+    /// Blend between uniform (0.0) and logarithmic (1.0) splits
+    pub split_lambda: f32,
+    /// How far in front of each cascade casters are still rendered
+    pub caster_margin: f32,
+    /// Subtracted from the receiver depth
+    pub bias: f32,
+    /// Receiver offset along its normal, in texels
+    pub normal_offset: f32,
+    /// PCF kernel is (2 * radius + 1)^2 taps
+    pub pcf_radius: u32,
+
+    splits: [f32; MAX_CASCADES],
+    texel_sizes: [f32; MAX_CASCADES],
+    matrices: [Mat4; MAX_CASCADES],
+}
+
+impl CascadedShadows {
+    /// `cascades` is clamped to `1..=MAX_CASCADES`
+    pub fn new(vk: Arc<Vk>, size: u32, cascades: usize) -> Self {
+        let cascades = cascades.clamp(1, MAX_CASCADES);
+
+        Self {
+            map: ShadowMap::new(vk, size, cascades as u32),
+            cascades,
+            direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            max_distance: 200.0,
+            split_lambda: 0.75,
+            caster_margin: 100.0,
+            bias: 0.0005,
+            normal_offset: 1.5,
+            pcf_radius: 1,
+
+            splits: [0.0; MAX_CASCADES],
+            texel_sizes: [0.0; MAX_CASCADES],
+            matrices: [Mat4::IDENTITY; MAX_CASCADES],
+        }
+    }
+
+    /// Fits every cascade to its slice of the camera frustum
+    pub fn update(&mut self, camera: &Camera) {
+        let view = camera.view;
+        let inverse = (camera.proj * view).inverse();
+
+        /* world space corners of the near and far planes, then their distance along the camera direction */
+        let corner = |x: f32, y: f32, z: f32| inverse.project_point3(Vec3::new(x, y, z));
+        let near_corners = [corner(-1.0, -1.0, 0.0), corner(1.0, -1.0, 0.0), corner(-1.0, 1.0, 0.0), corner(1.0, 1.0, 0.0)];
+        let far_corners = [corner(-1.0, -1.0, 1.0), corner(1.0, -1.0, 1.0), corner(-1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0)];
+
+        let near = view.transform_point3(near_corners[0]).z.abs();
+        let far = view.transform_point3(far_corners[0]).z.abs();
+        let end = far.min(self.max_distance);
+
+        let direction = self.direction.normalize();
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+        let size = self.map.size as f32;
+
+        let mut start = near;
+        for i in 0..self.cascades {
+            /* practical split scheme, between logarithmic and uniform */
+            let p = (i + 1) as f32 / self.cascades as f32;
+            let log = near * (end / near).powf(p);
+            let uniform = near + (end - near) * p;
+            let split = self.split_lambda * log + (1.0 - self.split_lambda) * uniform;
+
+            let t0 = (start - near) / (far - near);
+            let t1 = (split - near) / (far - near);
+            let corners = near_corners
+                .iter()
+                .zip(far_corners.iter())
+                .flat_map(|(n, f)| [n.lerp(*f, t0), n.lerp(*f, t1)])
+                .collect::<Vec<_>>();
+
+            /* a bounding sphere keeps the cascade size constant while the camera turns */
+            let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+            let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            let eye = center - direction * (radius + self.caster_margin);
+            let light_view = Mat4::look_at_rh(eye, center, up);
+            let mut light_proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, 2.0 * radius + self.caster_margin);
+
+            /* snap to whole texels so the shadow edges do not shimmer when the camera moves */
+            let origin = (light_proj * light_view * Vec4::W).truncate().truncate() * size / 2.0;
+            let offset = (origin.round() - origin) * 2.0 / size;
+            light_proj.w_axis.x += offset.x;
+            light_proj.w_axis.y += offset.y;
+
+            self.splits[i] = split;
+            self.texel_sizes[i] = 2.0 * radius / size;
+            self.matrices[i] = light_proj * light_view;
+
+            start = split;
+        }
+    }
+
+    /// View depth where each cascade ends
+    pub fn splits(&self) -> &[f32] {
+        &self.splits[..self.cascades]
+    }
+
+    pub fn matrices(&self) -> &[Mat4] {
+        &self.matrices[..self.cascades]
+    }
+
+    /// Renders every cascade, call `update` first
+    pub fn build_commands<'a>(
+        &self,
+        vk: Arc<Vk>,
+        builder: &mut BuilderType,
+        meshes: impl IntoIterator<Item = &'a Mesh> + Clone,
+    ) -> Result<(), BindError> {
+        for (i, matrix) in self.matrices().iter().enumerate() {
+            self.map.build_commands(vk.clone(), builder, i as u32, *matrix, meshes.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Uniform data for `directional_shadow` in `shaders/shadow.glsl`
+    pub fn data(&self) -> DirectionalShadowData {
+        DirectionalShadowData {
+            matrices: self.matrices.map(|m| m.to_cols_array_2d()),
+            splits: self.splits,
+            texel_sizes: self.texel_sizes,
+            direction: self.direction.normalize().extend(0.0).to_array(),
+            params: [self.cascades as f32, self.bias, self.normal_offset, self.pcf_radius as f32],
+        }
+    }
+}
+
+/// Single perspective shadow map for a spot light
+pub struct SpotShadow {
+    pub map: ShadowMap,
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Half angle of the cone, in radians
+    pub angle: f32,
+    pub near: f32,
+    /// Distance the light reaches
+    pub range: f32,
+    pub bias: f32,
+    pub pcf_radius: u32,
+}
+
+impl SpotShadow {
+    pub fn new(vk: Arc<Vk>, size: u32) -> Self {
+        Self {
+            map: ShadowMap::new(vk, size, 1),
+            position: Vec3::ZERO,
+            direction: Vec3::NEG_Y,
+            angle: 30.0f32.to_radians(),
+            near: 0.1,
+            range: 50.0,
+            bias: 0.0001,
+            pcf_radius: 1,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        let direction = self.direction.normalize();
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+
+        Mat4::perspective_rh(2.0 * self.angle, 1.0, self.near, self.range)
+            * Mat4::look_at_rh(self.position, self.position + direction, up)
+    }
+
+    pub fn build_commands<'a>(&self, vk: Arc<Vk>, builder: &mut BuilderType, meshes: impl IntoIterator<Item = &'a Mesh>) -> Result<(), BindError> {
+        self.map.build_commands(vk, builder, 0, self.matrix(), meshes)
+    }
+
+    /// Uniform data for `spot_shadow` in `shaders/shadow.glsl`
+    pub fn data(&self) -> SpotShadowData {
+        SpotShadowData {
+            matrix: self.matrix().to_cols_array_2d(),
+            params: [0.0, self.bias, 0.0, self.pcf_radius as f32],
+        }
+    }
+}
+
+/// 32 bit depth when it can be sampled, the always supported D16_UNORM otherwise
+fn shadow_format(vk: Arc<Vk>) -> Format {
+    let supported = vk.physical_device
+        .format_properties(Format::D32_SFLOAT)
+        .unwrap()
+        .optimal_tiling_features
+        .contains(FormatFeatures::DEPTH_STENCIL_ATTACHMENT | FormatFeatures::SAMPLED_IMAGE);
+
+    if supported { Format::D32_SFLOAT } else { Format::D16_UNORM }
+}
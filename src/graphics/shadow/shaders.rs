@@ -0,0 +1,21 @@
+/// Depth only, same inputs and sets as the usual `Mesh` shaders but the light matrix in a push constant
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/depth.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/depth.vert");
+}
+
+/// `vs` for meshes with `Mesh::set_instance_transforms`
+pub mod instanced_vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        path: "src/graphics/shaders/depth_instanced.vert",
+    }
+
+    pub const SOURCE: &str = include_str!("../shaders/depth_instanced.vert");
+}
@@ -0,0 +1,133 @@
+use std::{path::Path, sync::Arc};
+
+use glam::{Mat3, Mat4};
+use vulkano::{descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, format::{Format, FormatFeatures}, image::{sampler::{Filter, SamplerAddressMode, SamplerMipmapMode}, view::{ImageView, ImageViewCreateInfo, ImageViewType}, ImageUsage}, pipeline::{graphics::{depth_stencil::CompareOp, viewport::Viewport}, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::RenderPass};
+
+use super::{cache::SamplerDesc, compute::{ComputeBindings, Dispatch, VkComputePipeline}, pipeline::GraphicsPipelineBuilder, camera::Camera, command::BuilderType, image::{TextureError, TextureOptions, VkImage}, utils::descriptor_set, vk::Vk};
+
+pub mod shaders;
+
+/// Converts an equirectangular (latitude/longitude) image into a `size`x`size` cubemap on the GPU.
+/// Returns the R16G16B16A16_SFLOAT cubemap with a `Cube` view
+pub fn equirect_to_cubemap(vk: Arc<Vk>, equirect: Arc<ImageView>, size: u32) -> (VkImage, Arc<ImageView>) {
+    let cs = shaders::equirect_cs::load(vk.device.clone()).unwrap();
+    let pipeline = VkComputePipeline::new(vk.clone(), cs);
+
+    let cubemap = VkImage::cubemap(vk.allocators.clone(), Format::R16G16B16A16_SFLOAT, size, 1, ImageUsage::STORAGE);
+    let faces = vk.image_views.get(
+        &cubemap.content,
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Dim2dArray,
+            usage: ImageUsage::STORAGE,
+            ..ImageViewCreateInfo::from_image(&cubemap.content)
+        },
+    );
+
+    /* 32 bit float images are not required to support linear filtering */
+    let linear = vk.physical_device
+        .format_properties(equirect.format())
+        .unwrap()
+        .optimal_tiling_features
+        .intersects(FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR);
+    let filter = if linear { Filter::Linear } else { Filter::Nearest };
+
+    let sampler = vk.samplers.get(SamplerDesc {
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_mode: SamplerMipmapMode::Nearest,
+        ..SamplerDesc::linear().address_mode(SamplerAddressMode::ClampToEdge)
+    });
+
+    pipeline.run(
+        vk.clone(),
+        ComputeBindings::new()
+            .sampled_image(0, equirect, sampler)
+            .image(1, faces),
+        Dispatch::Invocations([size, size, 6]),
+    ).unwrap();
+
+    let view = cubemap.cube_view(vk);
+
+    (cubemap, view)
+}
+
+/// Draws a cubemap behind the scene, inside the render pass from `render_pass_with_depth`
+pub struct Skybox {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub cubemap: Arc<ImageView>,
+    set: Arc<PersistentDescriptorSet>,
+}
+
+impl Skybox {
+    /// `cubemap` must be a `Cube` view, see `VkImage::from_cube_faces` and `equirect_to_cubemap`
+    pub fn new(vk: Arc<Vk>, render_pass: Arc<RenderPass>, cubemap: Arc<ImageView>) -> Self {
+        let vs = shaders::skyvs::load(vk.device.clone()).unwrap();
+        let fs = shaders::skyfs::load(vk.device.clone()).unwrap();
+
+        /* no vertex buffers, the cube comes from gl_VertexIndex.
+        the sky sits at depth 1.0, only visible where nothing else was drawn */
+        let pipeline = GraphicsPipelineBuilder::new(vs, fs)
+            .depth_compare(CompareOp::LessOrEqual)
+            .depth_write(false)
+            .dynamic_state(DynamicState::Viewport)
+            .build(vk.clone(), render_pass);
+
+        let sampler = vk.samplers.get(SamplerDesc::linear().address_mode(SamplerAddressMode::ClampToEdge));
+
+        let set = descriptor_set(
+            vk.clone(),
+            0,
+            pipeline.clone(),
+            [WriteDescriptorSet::image_view_sampler(0, cubemap.clone(), sampler)],
+        ).0;
+
+        Self {
+            pipeline,
+            cubemap,
+            set,
+        }
+    }
+
+    /// Loads an equirectangular HDR (or LDR) panorama and converts it to a `face_size` cubemap
+    pub fn from_equirect_file(
+        vk: Arc<Vk>,
+        render_pass: Arc<RenderPass>,
+        path: impl AsRef<Path>,
+        face_size: u32,
+    ) -> Result<Self, TextureError> {
+        let (_, equirect) = VkImage::from_file(vk.clone(), path, TextureOptions::default())?;
+        let (_, cubemap) = equirect_to_cubemap(vk.clone(), equirect, face_size);
+
+        Ok(Self::new(vk, render_pass, cubemap))
+    }
+
+    /// Camera projection * view with the translation removed, so the sky never gets closer
+    pub fn view_proj(camera: &Camera) -> Mat4 {
+        camera.proj * Mat4::from_mat3(Mat3::from_mat4(camera.view))
+    }
+
+    /// Warning: this binds the skybox pipeline, rebind yours before drawing anything else.
+    ///
+    /// `extent` is the framebuffer size
+    pub fn build_commands(&self, builder: &mut BuilderType, camera: &Camera, extent: [u32; 2]) {
+        let pc = shaders::skyvs::SkyPC {
+            view_proj: Self::view_proj(camera).to_cols_array_2d(),
+        };
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .set_viewport(0, [Viewport {
+                offset: [0.0, 0.0],
+                extent: [extent[0] as f32, extent[1] as f32],
+                depth_range: 0.0..=1.0,
+            }].into_iter().collect())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, pc)
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, self.set.clone())
+            .unwrap()
+            .draw(36, 1, 0, 0)
+            .unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+pub mod skyvs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(push_constant) uniform SkyPC {
+                mat4 view_proj; // camera projection * view without translation
+            };
+
+            layout(location = 0) out vec3 dir;
+
+            const vec3 corners[8] = vec3[8](
+                vec3(-1, -1, -1), vec3( 1, -1, -1), vec3( 1,  1, -1), vec3(-1,  1, -1),
+                vec3(-1, -1,  1), vec3( 1, -1,  1), vec3( 1,  1,  1), vec3(-1,  1,  1)
+            );
+
+            const int indices[36] = int[36](
+                0, 1, 2,  2, 3, 0, // -Z
+                4, 6, 5,  6, 4, 7, // +Z
+                0, 3, 7,  7, 4, 0, // -X
+                1, 5, 6,  6, 2, 1, // +X
+                3, 2, 6,  6, 7, 3, // +Y
+                0, 4, 5,  5, 1, 0  // -Y
+            );
+
+            void main() {
+                dir = corners[indices[gl_VertexIndex]];
+                vec4 clip = view_proj * vec4(dir, 1.0);
+
+                // z = w puts the sky on the far plane, behind everything else
+                gl_Position = clip.xyww;
+            }
+        ",
+    }
+}
+
+pub mod skyfs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform samplerCube sky;
+
+            layout(location = 0) in vec3 dir;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(texture(sky, dir).rgb, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod equirect_cs {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_x_id = 100, local_size_y_id = 101) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D equirect;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray faces;
+
+            const float PI = 3.14159265359;
+
+            // direction through texel uv of a cube face, in Vulkan face order +X -X +Y -Y +Z -Z
+            vec3 face_dir(uint face, vec2 uv) {
+                switch (face) {
+                    case 0: return vec3( 1.0, -uv.y, -uv.x);
+                    case 1: return vec3(-1.0, -uv.y,  uv.x);
+                    case 2: return vec3( uv.x,  1.0,  uv.y);
+                    case 3: return vec3( uv.x, -1.0, -uv.y);
+                    case 4: return vec3( uv.x, -uv.y,  1.0);
+                    default: return vec3(-uv.x, -uv.y, -1.0);
+                }
+            }
+
+            void main() {
+                ivec3 id = ivec3(gl_GlobalInvocationID);
+                ivec2 size = imageSize(faces).xy;
+                if (id.x >= size.x || id.y >= size.y) {
+                    return;
+                }
+
+                vec2 uv = (vec2(id.xy) + 0.5) / vec2(size) * 2.0 - 1.0;
+                vec3 dir = normalize(face_dir(id.z, uv));
+
+                vec2 eq_uv = vec2(atan(dir.z, dir.x) / (2.0 * PI) + 0.5, 0.5 - asin(dir.y) / PI);
+
+                imageStore(faces, id, vec4(textureLod(equirect, eq_uv, 0.0).rgb, 1.0));
+            }
+        ",
+    }
+}
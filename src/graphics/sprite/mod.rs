@@ -0,0 +1,304 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2, Vec4};
+use vulkano::{buffer::BufferContents, descriptor_set::WriteDescriptorSet, image::view::ImageView, pipeline::{graphics::{vertex_input::Vertex, viewport::Viewport}, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::RenderPass};
+
+use super::{buffer::VkIterBuffer, cache::SamplerDesc, command::BuilderType, mesh::material::white_texture, pipeline::{BlendPreset, GraphicsPipelineBuilder}, utils::descriptor_set, vk::Vk};
+
+pub mod shaders;
+
+const KIND_QUAD: u32 = 0;
+const KIND_ELLIPSE: u32 = 1;
+
+/// A quad spanning `origin + axis_x * u + axis_y * v` for u, v in [0, 1]
+#[derive(BufferContents, Vertex, Copy, Clone)]
+#[repr(C)]
+pub struct SpriteInstance {
+    #[format(R32G32B32_SFLOAT)]
+    pub origin: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub axis_x: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub axis_y: [f32; 3],
+    /// Min and max uv
+    #[format(R32G32B32A32_SFLOAT)]
+    pub uv_rect: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+    #[format(R32_UINT)]
+    pub kind: u32,
+    /// Ring width as a fraction of the radius, 0 fills the ellipse
+    #[format(R32_SFLOAT)]
+    pub thickness: f32,
+}
+
+/// A textured quad, see `SpriteBatch::sprite`
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
+    pub pos: Vec2,
+    pub size: Vec2,
+    /// Radians, around `pivot`
+    pub rotation: f32,
+    /// Point of the quad placed at `pos`, (0, 0) is the first corner and (1, 1) the opposite one
+    pub pivot: Vec2,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    pub color: Vec4,
+}
+
+impl Sprite {
+    pub fn new(pos: Vec2, size: Vec2) -> Self {
+        Self {
+            pos,
+            size,
+            rotation: 0.0,
+            pivot: Vec2::ZERO,
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            color: Vec4::ONE,
+        }
+    }
+
+    /// Uses the pixels `min..max` of a `texture_size` texture, e.g. a frame of an atlas
+    pub fn region(mut self, min: Vec2, max: Vec2, texture_size: Vec2) -> Self {
+        self.uv_min = min / texture_size;
+        self.uv_max = max / texture_size;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn pivot(mut self, pivot: Vec2) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn color(mut self, color: Vec4) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+struct Item {
+    layer: i32,
+    texture: Option<Arc<ImageView>>,
+    instance: SpriteInstance,
+}
+
+/// Batches 2D sprites, rects, circles and polylines into one instance buffer.
+///
+/// Primitives live in a 2D space mapped by `transform` (when they are added) into 3D,
+/// then by the `view_proj` given to `build_commands`:
+/// - HUD: identity `transform` and `SpriteBatch::screen(width, height)`, coordinates in pixels, y down
+/// - world space: `transform` places the plane, `camera.proj * camera.view`
+///
+/// Lower `layer`s are drawn first. Inside a layer, primitives are grouped by texture
+/// (one descriptor bind and one instanced draw per group) and keep their order within a group.
+pub struct SpriteBatch {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub sampler: SamplerDesc,
+
+    /// Applies to the primitives added afterwards
+    pub transform: Mat4,
+    /// Applies to the primitives added afterwards
+    pub layer: i32,
+
+    items: Vec<Item>,
+}
+
+impl SpriteBatch {
+    /// `depth_test` only matters when `render_pass` has a depth attachment, the batch never writes depth
+    pub fn new(vk: Arc<Vk>, render_pass: Arc<RenderPass>, depth_test: bool) -> Self {
+        let pipeline = GraphicsPipelineBuilder::new(
+            shaders::vs::load(vk.device.clone()).unwrap(),
+            shaders::fs::load(vk.device.clone()).unwrap(),
+        )
+            .instance::<SpriteInstance>()
+            .blend(BlendPreset::Alpha)
+            .depth_test(depth_test)
+            .depth_write(false)
+            .dynamic_state(DynamicState::Viewport)
+            .build(vk, render_pass);
+
+        Self {
+            pipeline,
+            sampler: SamplerDesc::linear(),
+
+            transform: Mat4::IDENTITY,
+            layer: 0,
+
+            items: vec![],
+        }
+    }
+
+    /// Pixel coordinates with (0, 0) at the top left corner
+    pub fn screen(width: f32, height: f32) -> Mat4 {
+        Mat4::orthographic_rh(0.0, width, 0.0, height, -1.0, 1.0)
+    }
+
+    /// Removes every primitive
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn sprite(&mut self, texture: Arc<ImageView>, sprite: Sprite) {
+        self.push_sprite(Some(texture), sprite, KIND_QUAD, 0.0);
+    }
+
+    /// Untextured rectangle from `pos` to `pos + size`
+    pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Vec4) {
+        self.push_sprite(None, Sprite::new(pos, size).color(color), KIND_QUAD, 0.0);
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f32, color: Vec4) {
+        self.ellipse(center, Vec2::splat(radius), 0.0, color);
+    }
+
+    /// Circle outline `width` wide, inside the radius
+    pub fn ring(&mut self, center: Vec2, radius: f32, width: f32, color: Vec4) {
+        self.ellipse(center, Vec2::splat(radius), (width / radius).clamp(0.0, 1.0), color);
+    }
+
+    pub fn line(&mut self, a: Vec2, b: Vec2, width: f32, color: Vec4) {
+        let dir = b - a;
+        let normal = dir.perp().normalize_or_zero() * width;
+
+        self.push(None, SpriteInstance {
+            origin: [0.0; 3],
+            axis_x: [0.0; 3],
+            axis_y: [0.0; 3],
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: color.to_array(),
+            kind: KIND_QUAD,
+            thickness: 0.0,
+        }, a - normal * 0.5, dir, normal);
+    }
+
+    /// Segments between consecutive points with round joins, back to the first point when `closed`
+    pub fn polyline(&mut self, points: &[Vec2], width: f32, color: Vec4, closed: bool) {
+        if points.len() < 2 {
+            return;
+        }
+
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], width, color);
+        }
+        if closed {
+            self.line(points[points.len() - 1], points[0], width, color);
+        }
+
+        let joins = if closed { points } else { &points[1..points.len() - 1] };
+        for &point in joins {
+            self.circle(point, width * 0.5, color);
+        }
+    }
+
+    /// Uploads every primitive into one instance buffer and draws it.
+    /// Must be recorded inside the render pass given to `new`.
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, view_proj: Mat4, viewport: Viewport) {
+        if self.items.is_empty() {
+            return;
+        }
+
+        let mut items = self.items.iter().collect::<Vec<_>>();
+        items.sort_by_key(|item| (item.layer, item.texture.as_ref().map_or(0, |texture| Arc::as_ptr(texture) as usize)));
+
+        let instances = VkIterBuffer::vertex(vk.allocators.clone(), items.iter().map(|item| item.instance).collect());
+        let sampler = vk.samplers.get(self.sampler.clone());
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .set_viewport(0, [viewport].into_iter().collect())
+            .unwrap()
+            .push_constants(self.pipeline.layout().clone(), 0, shaders::vs::SpritePC {
+                view_proj: view_proj.to_cols_array_2d(),
+            })
+            .unwrap()
+            .bind_vertex_buffers(0, instances.content.clone())
+            .unwrap();
+
+        /* one draw per run of the same texture */
+        let mut first = 0;
+        while first < items.len() {
+            let texture = &items[first].texture;
+            let count = items[first..]
+                .iter()
+                .take_while(|item| same_texture(&item.texture, texture))
+                .count();
+
+            let view = texture.clone().unwrap_or_else(|| white_texture(vk.clone()));
+            let set = descriptor_set(
+                vk.clone(),
+                0,
+                self.pipeline.clone(),
+                [WriteDescriptorSet::image_view_sampler(0, view, sampler.clone())],
+            ).0;
+
+            builder
+                .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, set)
+                .unwrap()
+                .draw(6, count as u32, 0, first as u32)
+                .unwrap();
+
+            first += count;
+        }
+    }
+
+    fn ellipse(&mut self, center: Vec2, radii: Vec2, thickness: f32, color: Vec4) {
+        let sprite = Sprite::new(center, radii * 2.0)
+            .pivot(Vec2::splat(0.5))
+            .color(color);
+
+        self.push_sprite(None, sprite, KIND_ELLIPSE, thickness);
+    }
+
+    fn push_sprite(&mut self, texture: Option<Arc<ImageView>>, sprite: Sprite, kind: u32, thickness: f32) {
+        let (sin, cos) = sprite.rotation.sin_cos();
+        let axis_x = Vec2::new(cos, sin) * sprite.size.x;
+        let axis_y = Vec2::new(-sin, cos) * sprite.size.y;
+        let origin = sprite.pos - axis_x * sprite.pivot.x - axis_y * sprite.pivot.y;
+
+        self.push(texture, SpriteInstance {
+            origin: [0.0; 3],
+            axis_x: [0.0; 3],
+            axis_y: [0.0; 3],
+            uv_rect: [sprite.uv_min.x, sprite.uv_min.y, sprite.uv_max.x, sprite.uv_max.y],
+            color: sprite.color.to_array(),
+            kind,
+            thickness,
+        }, origin, axis_x, axis_y);
+    }
+
+    /// Places the quad through `transform`
+    fn push(&mut self, texture: Option<Arc<ImageView>>, mut instance: SpriteInstance, origin: Vec2, axis_x: Vec2, axis_y: Vec2) {
+        instance.origin = self.transform.transform_point3(origin.extend(0.0)).to_array();
+        instance.axis_x = self.transform.transform_vector3(axis_x.extend(0.0)).to_array();
+        instance.axis_y = self.transform.transform_vector3(axis_y.extend(0.0)).to_array();
+
+        self.items.push(Item {
+            layer: self.layer,
+            texture,
+            instance,
+        });
+    }
+}
+
+fn same_texture(a: &Option<Arc<ImageView>>, b: &Option<Arc<ImageView>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
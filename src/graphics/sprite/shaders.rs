@@ -0,0 +1,86 @@
+/// One quad per `SpriteInstance`, 6 vertices from `gl_VertexIndex`
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        src: r"
+            #version 460
+
+            layout(location = 0) in vec3 origin;
+            layout(location = 1) in vec3 axis_x;
+            layout(location = 2) in vec3 axis_y;
+            layout(location = 3) in vec4 uv_rect;
+            layout(location = 4) in vec4 color;
+            layout(location = 5) in uint kind;
+            layout(location = 6) in float thickness;
+
+            layout(push_constant) uniform SpritePC {
+                mat4 view_proj;
+            };
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) out vec2 v_local;
+            layout(location = 2) out vec4 v_color;
+            layout(location = 3) flat out uint v_kind;
+            layout(location = 4) flat out float v_thickness;
+
+            const vec2 CORNERS[6] = vec2[](
+                vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+                vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+            );
+
+            void main() {
+                vec2 corner = CORNERS[gl_VertexIndex];
+                vec3 pos = origin + axis_x * corner.x + axis_y * corner.y;
+
+                v_uv = mix(uv_rect.xy, uv_rect.zw, corner);
+                v_local = corner;
+                v_color = color;
+                v_kind = kind;
+                v_thickness = thickness;
+
+                gl_Position = view_proj * vec4(pos, 1.0);
+            }
+        ",
+    }
+}
+
+pub mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        src: r"
+            #version 460
+
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) in vec2 v_local;
+            layout(location = 2) in vec4 v_color;
+            layout(location = 3) flat in uint v_kind;
+            layout(location = 4) flat in float v_thickness;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                vec4 color = texture(tex, v_uv) * v_color;
+
+                // ellipse filling the quad, antialiased over one pixel
+                if (v_kind == 1) {
+                    float d = length(v_local * 2.0 - 1.0);
+                    float aa = fwidth(d);
+
+                    float coverage = 1.0 - smoothstep(1.0 - aa, 1.0, d);
+                    if (v_thickness > 0.0) {
+                        coverage *= smoothstep(1.0 - v_thickness - aa, 1.0 - v_thickness, d);
+                    }
+                    color.a *= coverage;
+                }
+
+                if (color.a <= 0.0) {
+                    discard;
+                }
+
+                f_color = color;
+            }
+        ",
+    }
+}
@@ -0,0 +1,55 @@
+use imgui::Ui;
+
+use super::mesh::mesh::Mesh;
+
+/// Counters of what a frame recorded, reset at the start of every frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// Meshes considered for drawing
+    pub meshes: u32,
+    /// Meshes skipped by frustum culling, see `culling::visible_meshes`
+    pub culled_meshes: u32,
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub triangles: u64,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Adds one draw of `mesh_triangles` triangles, `instances` times
+    pub fn draw(&mut self, mesh_triangles: u32, instances: u32) {
+        self.draw_calls += 1;
+        self.instances += instances;
+        self.triangles += mesh_triangles as u64 * instances as u64;
+    }
+
+    /// Adds the draws of `Mesh::build_commands`, one per level of detail group
+    pub fn mesh(&mut self, mesh: &Mesh) {
+        if mesh.lods.groups.is_empty() {
+            self.draw(mesh.index_count(mesh.lods.current) / 3, mesh.instance_count());
+        }
+
+        for group in &mesh.lods.groups {
+            self.draw(mesh.index_count(group.level) / 3, group.count);
+        }
+    }
+
+    pub fn ui(&self, ui: &Ui) {
+        ui.window("frame stats")
+            .size([220.0, 140.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                ui.text(format!("meshes: {}", self.meshes));
+                ui.text(format!("culled: {}", self.culled_meshes));
+                ui.text(format!("draw calls: {}", self.draw_calls));
+                ui.text(format!("instances: {}", self.instances));
+                ui.text(format!("triangles: {}", self.triangles));
+            });
+    }
+}
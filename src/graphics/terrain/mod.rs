@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2, Vec3};
+use imgui::Ui;
+use vulkano::{buffer::{BufferContents, Subbuffer}, descriptor_set::WriteDescriptorSet, image::view::ImageView, pipeline::{graphics::viewport::Viewport, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint}, render_pass::RenderPass};
+
+use super::{buffer::{VkBuffer, VkIterBuffer}, cache::SamplerDesc, camera::Camera, command::{submit_cmd_buf, BuilderType, VkBuilder}, compute::{ComputeBindings, Dispatch, VkComputePipeline}, culling::Frustum, mesh::lod::select_level, pipeline::GraphicsPipelineBuilder, utils::descriptor_set, vk::Vk};
+
+pub mod shaders;
+
+/// Color of the heights between `min_height` and `max_height`, ids of `shaders/colormap.glsl`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Colormap {
+    Grayscale,
+    #[default]
+    Viridis,
+    /// Diverging, for signed fields
+    Coolwarm,
+    Terrain,
+}
+
+/// `TerrainInfo` uniform of `shaders/terrain.glsl`
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct TerrainInfo {
+    pub model: [[f32; 4]; 4],
+    pub size: [u32; 2],
+    pub cell_size: [f32; 2],
+    pub height_scale: f32,
+    pub min_height: f32,
+    pub max_height: f32,
+    pub colormap_id: u32,
+    pub light: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainSettings {
+    /// World size of one grid cell on x and z
+    pub cell_size: Vec2,
+    pub height_scale: f32,
+    /// Range of the colormap, also used to bound the chunks for culling and level selection
+    pub min_height: f32,
+    pub max_height: f32,
+    pub colormap: Colormap,
+    /// Direction the light travels in
+    pub light_direction: Vec3,
+    pub ambient: f32,
+
+    /// Chunks covering more than this fraction of the screen height use the full grid,
+    /// every level halves it and skips every other vertex
+    pub lod_screen_size: f32,
+    pub hysteresis: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: Vec2::ONE,
+            height_scale: 1.0,
+            min_height: 0.0,
+            max_height: 1.0,
+            colormap: Colormap::default(),
+            light_direction: Vec3::new(-0.4, -1.0, -0.3),
+            ambient: 0.2,
+
+            lod_screen_size: 0.5,
+            hysteresis: 0.15,
+        }
+    }
+}
+
+/// Square of `chunk_size` cells, drawn every `1 << level` vertices
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainChunk {
+    /// First grid vertex
+    pub origin: [u32; 2],
+    pub level: usize,
+}
+
+/// Heightmap drawn as chunked grids, centered on `position`.
+///
+/// Heights live in a storage buffer read by the vertex shader, so a compute shader can write them
+/// every frame (see `heights`) without rebuilding anything. Chunks share one index buffer per level
+/// and pick their level from their screen size, edges next to coarser chunks are snapped onto them.
+///
+/// ```ignore
+/// let mut terrain = Terrain::new(vk.clone(), render_pass.clone(), [257, 257], &heights, 32, TerrainSettings::default());
+///
+/// terrain.update(&camera);
+/// // outside of the render pass, optionally
+/// simulation.bind(vk.clone(), &mut builder, ComputeBindings::new().buffer(0, terrain.heights()));
+/// // inside
+/// terrain.build_commands(vk.clone(), &mut builder, &camera, viewport);
+/// ```
+pub struct Terrain {
+    pub pipeline: Arc<GraphicsPipeline>,
+    pub settings: TerrainSettings,
+    pub position: Vec3,
+
+    size: [u32; 2],
+    chunk_size: u32,
+    heights: VkIterBuffer<f32>,
+    level_indices: Vec<VkIterBuffer<u32>>,
+    chunks: Vec<TerrainChunk>,
+    chunk_counts: [u32; 2],
+}
+
+impl Terrain {
+    /// `heights` is a `size[0]` by `size[1]` grid, x first. `chunk_size` must be a power of two
+    pub fn new(
+        vk: Arc<Vk>,
+        render_pass: Arc<RenderPass>,
+        size: [u32; 2],
+        heights: &[f32],
+        chunk_size: u32,
+        settings: TerrainSettings,
+    ) -> Self {
+        assert_eq!(heights.len(), (size[0] * size[1]) as usize, "one height per grid vertex");
+        assert!(chunk_size.is_power_of_two(), "chunk_size must be a power of two");
+        assert!(size[0] >= 2 && size[1] >= 2, "the grid needs at least 2 vertices per side");
+
+        let pipeline = GraphicsPipelineBuilder::new(
+            shaders::vs::load(vk.device.clone()).unwrap(),
+            shaders::fs::load(vk.device.clone()).unwrap(),
+        )
+            .dynamic_state(DynamicState::Viewport)
+            .build(vk.clone(), render_pass);
+
+        /* level l has chunk_size >> l cells per side */
+        let level_indices = (0..=chunk_size.trailing_zeros())
+            .map(|level| VkIterBuffer::index(vk.allocators.clone(), grid_indices(chunk_size >> level)))
+            .collect();
+
+        let chunk_counts = [
+            (size[0] - 1).div_ceil(chunk_size),
+            (size[1] - 1).div_ceil(chunk_size),
+        ];
+        let chunks = (0..chunk_counts[1])
+            .flat_map(|z| (0..chunk_counts[0]).map(move |x| TerrainChunk {
+                origin: [x * chunk_size, z * chunk_size],
+                level: 0,
+            }))
+            .collect();
+
+        Self {
+            pipeline,
+            settings,
+            position: Vec3::ZERO,
+
+            size,
+            chunk_size,
+            heights: VkIterBuffer::storage(vk.allocators.clone(), heights.iter().copied()),
+            level_indices,
+            chunks,
+            chunk_counts,
+        }
+    }
+
+    /// Heights from the red channel of `heightmap`, sampled at `size[0]` by `size[1]` points
+    pub fn from_texture(
+        vk: Arc<Vk>,
+        render_pass: Arc<RenderPass>,
+        heightmap: Arc<ImageView>,
+        size: [u32; 2],
+        chunk_size: u32,
+        settings: TerrainSettings,
+    ) -> Self {
+        let terrain = Self::new(vk.clone(), render_pass, size, &vec![0.0; (size[0] * size[1]) as usize], chunk_size, settings);
+
+        let cs = shaders::from_texture_cs::load(vk.device.clone()).unwrap();
+        let pipeline = VkComputePipeline::new(vk.clone(), cs);
+
+        let mut builder = VkBuilder::new_once(vk.clone());
+        let bindings = ComputeBindings::new()
+            .sampled_image(0, heightmap, vk.samplers.get(SamplerDesc::linear()))
+            .buffer(1, terrain.heights());
+
+        pipeline.bind(vk.clone(), &mut builder.0, bindings);
+        pipeline.push_constants(&mut builder.0, shaders::from_texture_cs::SamplePC { size });
+        pipeline.dispatch(&mut builder.0, Dispatch::Invocations([size[0], size[1], 1])).unwrap();
+
+        submit_cmd_buf(vk, builder.command_buffer())
+            .wait(None)
+            .unwrap();
+
+        terrain
+    }
+
+    /// Grid vertices on x and z
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn chunks(&self) -> &[TerrainChunk] {
+        &self.chunks
+    }
+
+    /// `float heights[]` storage buffer, x first. Compute shaders writing it must be recorded before `build_commands`.
+    pub fn heights(&self) -> Subbuffer<[f32]> {
+        self.heights.content.clone()
+    }
+
+    /// Uploads new heights from the CPU
+    pub fn set_heights(&mut self, vk: Arc<Vk>, heights: &[f32]) {
+        assert_eq!(heights.len(), (self.size[0] * self.size[1]) as usize, "one height per grid vertex");
+
+        self.heights = VkIterBuffer::storage(vk.allocators.clone(), heights.iter().copied());
+    }
+
+    pub fn model(&self) -> Mat4 {
+        Mat4::from_translation(self.position)
+    }
+
+    /// Picks the level of every chunk from its screen size
+    pub fn update(&mut self, camera: &Camera) {
+        let max_level = self.level_indices.len() - 1;
+        let thresholds = (0..max_level)
+            .map(|level| self.settings.lod_screen_size / (1 << level) as f32)
+            .collect::<Vec<_>>();
+
+        for i in 0..self.chunks.len() {
+            let (min, max) = self.chunk_bounds(&self.chunks[i]);
+            let center = (min + max) * 0.5;
+            let size = camera.screen_size(center, (max - min).length() * 0.5);
+
+            let chunk = &mut self.chunks[i];
+            chunk.level = select_level(&thresholds, chunk.level, size, self.settings.hysteresis);
+        }
+    }
+
+    /// Draws the chunks seen by `camera`, must be recorded inside the render pass given to `new`
+    pub fn build_commands(&self, vk: Arc<Vk>, builder: &mut BuilderType, camera: &Camera, viewport: Viewport) {
+        let settings = &self.settings;
+
+        let camera_ubo = VkBuffer::uniform(vk.allocators.clone(), shaders::vs::Camera {
+            view: camera.get_view(),
+            proj: camera.get_proj(),
+        });
+        let info_ubo = VkBuffer::uniform(vk.allocators.clone(), TerrainInfo {
+            model: self.model().to_cols_array_2d(),
+            size: self.size,
+            cell_size: settings.cell_size.to_array(),
+            height_scale: settings.height_scale,
+            min_height: settings.min_height,
+            max_height: settings.max_height,
+            colormap_id: settings.colormap as u32,
+            light: settings.light_direction.normalize().extend(settings.ambient).to_array(),
+        });
+
+        let camera_set = descriptor_set(vk.clone(), 0, self.pipeline.clone(), [WriteDescriptorSet::buffer(0, camera_ubo.content)]).0;
+        let terrain_set = descriptor_set(vk.clone(), 1, self.pipeline.clone(), [
+            WriteDescriptorSet::buffer(0, self.heights.content.clone()),
+            WriteDescriptorSet::buffer(1, info_ubo.content),
+        ]).0;
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .unwrap()
+            .set_viewport(0, [viewport].into_iter().collect())
+            .unwrap()
+            .bind_descriptor_sets(PipelineBindPoint::Graphics, self.pipeline.layout().clone(), 0, (camera_set, terrain_set))
+            .unwrap();
+
+        let frustum = camera.frustum();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if !self.chunk_visible(chunk, &frustum) {
+                continue;
+            }
+
+            let step = 1u32 << chunk.level;
+            let indices = &self.level_indices[chunk.level];
+
+            builder
+                .push_constants(self.pipeline.layout().clone(), 0, shaders::vs::ChunkPC {
+                    origin: chunk.origin,
+                    step,
+                    verts: (self.chunk_size >> chunk.level) + 1,
+                    neighbor_steps: self.neighbor_steps(i, step),
+                })
+                .unwrap()
+                .bind_index_buffer(indices.content.clone())
+                .unwrap()
+                .draw_indexed(indices.content.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &Ui) {
+        ui.window("terrain")
+            .size([260.0, 200.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                let settings = &mut self.settings;
+
+                let mut colormap = settings.colormap as usize;
+                if ui.combo_simple_string("colormap", &mut colormap, &["grayscale", "viridis", "coolwarm", "terrain"]) {
+                    settings.colormap = [Colormap::Grayscale, Colormap::Viridis, Colormap::Coolwarm, Colormap::Terrain][colormap];
+                }
+
+                ui.slider("height scale", 0.0, 50.0, &mut settings.height_scale);
+                ui.slider("lod screen size", 0.05, 2.0, &mut settings.lod_screen_size);
+
+                let mut levels = vec![0; self.level_indices.len()];
+                for chunk in &self.chunks {
+                    levels[chunk.level] += 1;
+                }
+                ui.text(format!("chunks per level: {:?}", levels));
+            });
+    }
+
+    /// World space box of `chunk`, using the height range of the settings
+    fn chunk_bounds(&self, chunk: &TerrainChunk) -> (Vec3, Vec3) {
+        let settings = &self.settings;
+        let half = Vec2::new(self.size[0] as f32 - 1.0, self.size[1] as f32 - 1.0) * 0.5;
+
+        let start = Vec2::new(chunk.origin[0] as f32, chunk.origin[1] as f32);
+        let end = (start + Vec2::splat(self.chunk_size as f32)).min(half * 2.0);
+
+        let (start, end) = ((start - half) * settings.cell_size, (end - half) * settings.cell_size);
+
+        /* grid y is world z */
+        let min = Vec3::new(start.x, settings.min_height * settings.height_scale, start.y);
+        let max = Vec3::new(end.x, settings.max_height * settings.height_scale, end.y);
+
+        (min.min(max) + self.position, min.max(max) + self.position)
+    }
+
+    fn chunk_visible(&self, chunk: &TerrainChunk, frustum: &Frustum) -> bool {
+        let (min, max) = self.chunk_bounds(chunk);
+
+        frustum.intersects_aabb(min, max)
+    }
+
+    /// Steps of the chunks on -x, +x, -z and +z, `step` where there is none
+    fn neighbor_steps(&self, i: usize, step: u32) -> [u32; 4] {
+        let [count_x, count_z] = self.chunk_counts;
+        let (x, z) = (i as u32 % count_x, i as u32 / count_x);
+
+        let step_at = |x: Option<u32>, z: Option<u32>| match (x, z) {
+            (Some(x), Some(z)) if x < count_x && z < count_z => 1 << self.chunks[(z * count_x + x) as usize].level,
+            _ => step,
+        };
+
+        [
+            step_at(x.checked_sub(1), Some(z)),
+            step_at(Some(x + 1), Some(z)),
+            step_at(Some(x), z.checked_sub(1)),
+            step_at(Some(x), Some(z + 1)),
+        ]
+    }
+}
+
+/// Two triangles per cell of a `cells` by `cells` grid, vertices x first
+fn grid_indices(cells: u32) -> Vec<u32> {
+    let verts = cells + 1;
+    let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+
+    for z in 0..cells {
+        for x in 0..cells {
+            let a = z * verts + x;
+            let b = a + 1;
+            let c = a + verts;
+            let d = c + 1;
+
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+
+    indices
+}
@@ -0,0 +1,136 @@
+/// Grid vertices of one chunk from `gl_VertexIndex`, heights read from the storage buffer.
+/// Edge vertices next to a coarser chunk are moved onto its edge so no cracks open.
+pub mod vs {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        include: ["src/graphics/shaders"],
+        src: r#"
+            #version 460
+
+            #include "terrain.glsl"
+
+            layout(set = 0, binding = 0) uniform Camera {
+                mat4 view;
+                mat4 proj;
+            };
+
+            layout(push_constant) uniform ChunkPC {
+                uvec2 origin;
+                uint step;
+                uint verts; // per side
+                uvec4 neighbor_steps; // -x, +x, -z, +z
+            };
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out float v_height;
+
+            // height of the coarser edge `s` cells apart, along `axis` through `g`
+            float snapped_height(uvec2 g, uint s, int axis) {
+                uint along = g[axis];
+                uint low = along / s * s;
+
+                uvec2 a = g;
+                uvec2 b = g;
+                a[axis] = low;
+                b[axis] = low + s;
+
+                return mix(height_at(a), height_at(b), float(along - low) / float(s));
+            }
+
+            void main() {
+                uvec2 local = uvec2(gl_VertexIndex % verts, gl_VertexIndex / verts);
+                uvec2 g = min(origin + local * step, terrain_info.size - 1u);
+
+                float h = height_at(g);
+                if (local.x == 0u && neighbor_steps.x > step) {
+                    h = snapped_height(g, neighbor_steps.x, 1);
+                } else if (local.x == verts - 1u && neighbor_steps.y > step) {
+                    h = snapped_height(g, neighbor_steps.y, 1);
+                }
+                if (local.y == 0u && neighbor_steps.z > step) {
+                    h = snapped_height(g, neighbor_steps.z, 0);
+                } else if (local.y == verts - 1u && neighbor_steps.w > step) {
+                    h = snapped_height(g, neighbor_steps.w, 0);
+                }
+
+                // central differences, one cell apart
+                uvec2 left = uvec2(max(g.x, 1u) - 1u, g.y);
+                uvec2 right = uvec2(g.x + 1u, g.y);
+                uvec2 back = uvec2(g.x, max(g.y, 1u) - 1u);
+                uvec2 front = uvec2(g.x, g.y + 1u);
+
+                float scale = terrain_info.height_scale;
+                float dx = (height_at(right) - height_at(left)) * scale / (2.0 * terrain_info.cell_size.x);
+                float dz = (height_at(front) - height_at(back)) * scale / (2.0 * terrain_info.cell_size.y);
+
+                vec2 xz = (vec2(g) - vec2(terrain_info.size - 1u) * 0.5) * terrain_info.cell_size;
+                vec4 world = terrain_info.model * vec4(xz.x, h * scale, xz.y, 1.0);
+
+                v_normal = mat3(terrain_info.model) * normalize(vec3(-dx, 1.0, -dz));
+                v_height = h;
+
+                gl_Position = proj * view * world;
+            }
+        "#,
+    }
+}
+
+/// Colormap of the height, lit by one directional light
+pub mod fs {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        include: ["src/graphics/shaders"],
+        src: r#"
+            #version 460
+
+            #include "terrain.glsl"
+            #include "colormap.glsl"
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in float v_height;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                float range = max(terrain_info.max_height - terrain_info.min_height, 1e-6);
+                vec3 albedo = colormap(terrain_info.colormap_id, (v_height - terrain_info.min_height) / range);
+
+                vec3 n = normalize(v_normal);
+                float diffuse = max(dot(n, -normalize(terrain_info.light.xyz)), 0.0);
+
+                f_color = vec4(albedo * (terrain_info.light.w + diffuse), 1.0);
+            }
+        "#,
+    }
+}
+
+/// Copies the red channel of a texture into the heights
+pub mod from_texture_cs {
+    vulkano_shaders::shader!{
+        ty: "compute",
+        src: r"
+            #version 460
+
+            layout(local_size_x = 8, local_size_y = 8, local_size_x_id = 100, local_size_y_id = 101) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D heightmap;
+
+            layout(set = 0, binding = 1) writeonly buffer Heights {
+                float heights[];
+            };
+
+            layout(push_constant) uniform SamplePC {
+                uvec2 size;
+            };
+
+            void main() {
+                uvec2 g = gl_GlobalInvocationID.xy;
+                if (any(greaterThanEqual(g, size))) {
+                    return;
+                }
+
+                heights[g.y * size.x + g.x] = texture(heightmap, (vec2(g) + 0.5) / vec2(size)).r;
+            }
+        ",
+    }
+}
@@ -2,9 +2,9 @@
 
 use std::sync::Arc;
 
-use vulkano::{descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, format::{ClearValue, Format}, image::{view::ImageView, Image, ImageCreateInfo, ImageUsage}, memory::allocator::AllocationCreateInfo, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, ColorBlendState}, depth_stencil::DepthStencilState, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass}, shader::ShaderModule, swapchain::Swapchain};
+use vulkano::{descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet}, format::{ClearValue, Format}, image::{view::ImageView, Image, ImageAspects, ImageCreateInfo, ImageUsage, SampleCount}, memory::allocator::AllocationCreateInfo, pipeline::{graphics::{color_blend::{ColorBlendAttachmentState, ColorBlendState}, depth_stencil::DepthStencilState, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition}, viewport::{Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, GraphicsPipeline, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::{AttachmentLoadOp, Framebuffer, FramebufferCreateInfo, RenderPass, Subpass}, shader::ShaderModule, swapchain::Swapchain};
 
-use super::{command::SecondaryCmdBufType, image::VkImage, vertex::{PosInstanceData, PosVertex}, vk::Vk};
+use super::{command::SecondaryCmdBufType, compute::VkComputePipeline, image::VkImage, pipeline::GraphicsPipelineBuilder, vertex::{ModelInstanceData, PosInstanceData, PosVertex}, vk::Vk};
 
 /// All the data necessary for constructing a secondary renderpass
 pub struct VkSecRenderpass {
@@ -14,14 +14,15 @@ pub struct VkSecRenderpass {
     pub clear_values: Vec<Option<ClearValue>>,
 }
 
-pub fn descriptor_set(
+/// Works for graphics and compute pipelines alike
+pub fn descriptor_set<P: Pipeline>(
     vk: Arc<Vk>, 
     set: usize,
-    graphics_pipeline: Arc<GraphicsPipeline>,
+    pipeline: Arc<P>,
     writes: impl IntoIterator<Item = WriteDescriptorSet>,
 ) -> (Arc<PersistentDescriptorSet>, usize) {
     let descriptor_set_allocator = vk.allocators.descriptor_set.clone();
-    let pipeline_layout = graphics_pipeline.layout();
+    let pipeline_layout = pipeline.layout();
     let descriptor_set_layouts = pipeline_layout.set_layouts();
 
     let descriptor_set_layout_index = set;
@@ -38,13 +39,14 @@ pub fn descriptor_set(
 }
 
 pub fn framebuffers(
+    vk: Arc<Vk>,
     rp: Arc<RenderPass>, 
     images: &[Arc<Image>]
 ) -> Vec<Arc<Framebuffer>> {
     images
         .iter()
         .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
+            let view = vk.image_views.get_default(image);
             Framebuffer::new(
                 rp.clone(),
                 FramebufferCreateInfo {
@@ -58,24 +60,46 @@ pub fn framebuffers(
 }
 
 /* TODO: make attachments an argument to this */
+/// Works with both `render_pass_with_depth` and `render_pass_with_depth_msaa`,
+/// the sample count and formats are read from the render pass
 pub fn framebuffers_with_depth(
     vk: Arc<Vk>,
     rp: Arc<RenderPass>, 
     images: &Vec<Arc<Image>>
 ) -> Vec<Arc<Framebuffer>> {
+    let attachments = rp.attachments();
+    let samples = attachments[0].samples;
+    let extent = images[0].extent();
+
     let depth_image = ImageView::new_default(
-            VkImage::depth(vk.allocators.clone(), Format::D16_UNORM, images[0].extent()).content
+        match samples {
+            SampleCount::Sample1 => VkImage::depth(vk.allocators.clone(), attachments[1].format, extent),
+            _ => VkImage::multisampled(vk.allocators.clone(), attachments[1].format, extent, samples, ImageUsage::DEPTH_STENCIL_ATTACHMENT),
+        }.content
     )
     .unwrap();
 
+    /* one multisampled color target shared by every framebuffer, resolved into the swapchain image */
+    let msaa_color = (samples != SampleCount::Sample1).then(|| {
+        ImageView::new_default(
+            VkImage::multisampled(vk.allocators.clone(), attachments[0].format, extent, samples, ImageUsage::COLOR_ATTACHMENT).content
+        )
+        .unwrap()
+    });
+
     images
         .iter()
         .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
+            let view = vk.image_views.get_default(image);
+            let attachments = match &msaa_color {
+                Some(msaa_color) => vec![msaa_color.clone(), depth_image.clone(), view],
+                None => vec![view, depth_image.clone()],
+            };
+
             Framebuffer::new(
                 rp.clone(),
                 FramebufferCreateInfo {
-                    attachments: vec![view, depth_image.clone()],
+                    attachments,
                     ..Default::default()
                 },
             )
@@ -84,6 +108,86 @@ pub fn framebuffers_with_depth(
         .collect::<Vec<_>>()
 }
 
+/// Highest sample count supported for color and depth attachments that is not above `requested`
+pub fn supported_samples(vk: Arc<Vk>, requested: u32) -> SampleCount {
+    let properties = vk.physical_device.properties();
+    let supported = properties.framebuffer_color_sample_counts
+        & properties.framebuffer_depth_sample_counts;
+
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|&samples| samples as u32 <= requested && supported.contains_enum(samples))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+/// Same as `render_pass_with_depth` with multisampled color and depth, resolved into the swapchain image.
+/// `samples` is lowered to what the device supports, see `supported_samples`.
+///
+/// Attachments are (multisampled color, depth, resolve), see `clear_values_with_depth`. Pipelines built with
+/// `GraphicsPipelineBuilder` on this pass get the matching `MultisampleState`
+pub fn render_pass_with_depth_msaa(vk: Arc<Vk>, swapchain: Option<Arc<Swapchain>>, samples: u32) -> Arc<RenderPass> {
+    let samples = supported_samples(vk.clone(), samples);
+    if samples == SampleCount::Sample1 {
+        return render_pass_with_depth(vk, swapchain);
+    }
+
+    let format = match swapchain {
+        Some(swapchain) => swapchain.image_format(),
+        None => Format::R8G8B8A8_UNORM,
+    };
+
+    vulkano::single_pass_renderpass!(vk.device.clone(),
+        attachments: {
+            msaa_color: {
+                format: format,
+                samples: samples as u32,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+
+            depth_attachment: {
+                format: Format::D16_UNORM,
+                samples: samples as u32,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+
+            color_attachment: {
+                format: format,
+                samples: 1,
+                load_op: DontCare,
+                store_op: Store,
+            }
+        },
+        pass: {
+            color: [msaa_color],
+            color_resolve: [color_attachment],
+            depth_stencil: {depth_attachment},
+        },
+    )
+    .unwrap()
+}
+
+/// Clear values for `render_pass_with_depth` and `render_pass_with_depth_msaa`, in attachment order:
+/// `color` for the color target, 1.0 for depth and none for the resolve target
+pub fn clear_values_with_depth(rp: &RenderPass, color: [f32; 4]) -> Vec<Option<ClearValue>> {
+    rp.attachments()
+        .iter()
+        .map(|attachment| match attachment.load_op {
+            AttachmentLoadOp::Clear if attachment.format.aspects().intersects(ImageAspects::DEPTH) => Some(1.0.into()),
+            AttachmentLoadOp::Clear => Some(color.into()),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn render_pass_with_depth(vk: Arc<Vk>, swapchain: Option<Arc<Swapchain>>) -> Arc<RenderPass> {
     /* 
     for now, single pass renderpasses will do the job.
@@ -121,6 +225,7 @@ pub fn render_pass_with_depth(vk: Arc<Vk>, swapchain: Option<Arc<Swapchain>>) ->
     .unwrap()
 }
 
+/// Single `PosVertex` binding, see `GraphicsPipelineBuilder` for anything else
 pub fn pipeline(
     vk: Arc<Vk>,
     vs: Arc<ShaderModule>,
@@ -129,53 +234,14 @@ pub fn pipeline(
     render_pass: Arc<RenderPass>,
     viewport: Viewport,
 ) -> Arc<GraphicsPipeline> {
-    let vs = vs.entry_point("main").unwrap();
-    let fs = fs.entry_point("main").unwrap();
-
-    let vertex_input_state = PosVertex::per_vertex()
-        .definition(&vs.info().input_interface)
-        .unwrap();
-
-    let stages = [
-        PipelineShaderStageCreateInfo::new(vs),
-        PipelineShaderStageCreateInfo::new(fs),
-    ];
-
-    let layout = PipelineLayout::new(
-        vk.device.clone(),
-        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-            .into_pipeline_layout_create_info(vk.device.clone())
-            .unwrap(),
-    )
-    .unwrap();
-
-    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-
-    GraphicsPipeline::new(
-        vk.device.clone(),
-        None,
-        GraphicsPipelineCreateInfo {
-            stages: stages.into_iter().collect(),
-            vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(ViewportState {
-                viewports: [viewport].into_iter().collect(),
-                ..Default::default()
-            }),
-            rasterization_state: Some(RasterizationState::default()),
-            multisample_state: Some(MultisampleState::default()),
-            color_blend_state: Some(ColorBlendState::with_attachment_states(
-                subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
-            )),
-            subpass: Some(subpass.into()),
-            depth_stencil_state: Some(DepthStencilState::simple_depth_test()),
-            ..GraphicsPipelineCreateInfo::layout(layout)
-        },
-    )
-    .unwrap()
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .viewport(viewport)
+        .build(vk, render_pass)
 }
 
+/// `PosVertex` at binding 0 and `PosInstanceData` at binding 1, see `GraphicsPipelineBuilder` for anything else.
+/// `Mesh::build_commands` needs the shaders reflected with `ReflectionCache::insert_glsl`
 pub fn instancing_pipeline(
     vk: Arc<Vk>,
     vs: Arc<ShaderModule>,
@@ -184,49 +250,31 @@ pub fn instancing_pipeline(
     render_pass: Arc<RenderPass>,
     viewport: Viewport,
 ) -> Arc<GraphicsPipeline> {
-    let vs = vs.entry_point("main").unwrap();
-    let fs = fs.entry_point("main").unwrap();
-
-    let vertex_input_state = [PosVertex::per_vertex(), PosInstanceData::per_instance()]
-        .definition(&vs.info().input_interface)
-        .unwrap();
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .instance::<PosInstanceData>()
+        .viewport(viewport)
+        .build(vk, render_pass)
+}
 
-    let stages = [
-        PipelineShaderStageCreateInfo::new(vs),
-        PipelineShaderStageCreateInfo::new(fs),
-    ];
+/// `PosVertex` at binding 0 and `ModelInstanceData` at binding 1, for meshes with `Mesh::set_instance_transforms`.
+/// `mesh::shaders::instanced_vs` and `instanced_fs` match it, their `SOURCE` goes to `ReflectionCache::insert_glsl`.
+pub fn model_instancing_pipeline(
+    vk: Arc<Vk>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
 
-    let layout = PipelineLayout::new(
-        vk.device.clone(),
-        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-            .into_pipeline_layout_create_info(vk.device.clone())
-            .unwrap(),
-    )
-    .unwrap();
+    render_pass: Arc<RenderPass>,
+    viewport: Viewport,
+) -> Arc<GraphicsPipeline> {
+    GraphicsPipelineBuilder::new(vs, fs)
+        .vertex::<PosVertex>()
+        .instance::<ModelInstanceData>()
+        .viewport(viewport)
+        .build(vk, render_pass)
+}
 
-    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
-
-    GraphicsPipeline::new(
-        vk.device.clone(),
-        None,
-        GraphicsPipelineCreateInfo {
-            stages: stages.into_iter().collect(),
-            vertex_input_state: Some(vertex_input_state),
-            input_assembly_state: Some(InputAssemblyState::default()),
-            viewport_state: Some(ViewportState {
-                viewports: [viewport].into_iter().collect(),
-                ..Default::default()
-            }),
-            rasterization_state: Some(RasterizationState::default()),
-            multisample_state: Some(MultisampleState::default()),
-            color_blend_state: Some(ColorBlendState::with_attachment_states(
-                subpass.num_color_attachments(),
-                ColorBlendAttachmentState::default(),
-            )),
-            subpass: Some(subpass.into()),
-            depth_stencil_state: Some(DepthStencilState::simple_depth_test()),
-            ..GraphicsPipelineCreateInfo::layout(layout)
-        },
-    )
-    .unwrap()
+/// See `VkComputePipeline` for specialization constants and dispatching
+pub fn compute_pipeline(vk: Arc<Vk>, cs: Arc<ShaderModule>) -> VkComputePipeline {
+    VkComputePipeline::new(vk, cs)
 }
\ No newline at end of file
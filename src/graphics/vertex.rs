@@ -1,3 +1,4 @@
+use glam::{Mat4, Vec4};
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 
 
@@ -13,4 +14,57 @@ pub struct PosVertex {
 pub struct PosInstanceData {
     #[format(R32G32B32_SFLOAT)]
     pub ofs: [f32; 3],
+}
+
+/// Full per instance transform, bound in place of `PosInstanceData` (see `Mesh::set_instance_transforms`).
+/// Matches `shaders/instance.glsl`, the matrix takes 4 locations.
+#[derive(BufferContents, Clone, Copy, Vertex)]
+#[repr(C)]
+pub struct ModelInstanceData {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_model: [[f32; 4]; 4],
+    /// Multiplies the mesh color
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_color: [f32; 4],
+    /// Free for the shaders, e.g. an id or a texture index
+    #[format(R32_UINT)]
+    pub instance_custom: u32,
+}
+
+impl ModelInstanceData {
+    pub fn new(transform: Mat4, color: Vec4, custom: u32) -> Self {
+        Self {
+            instance_model: transform.to_cols_array_2d(),
+            instance_color: color.to_array(),
+            instance_custom: custom,
+        }
+    }
+}
+
+impl From<Mat4> for ModelInstanceData {
+    fn from(transform: Mat4) -> Self {
+        Self::new(transform, Vec4::ONE, 0)
+    }
+}
+
+/// Shading attributes, kept in their own buffer next to `PosVertex` (binding 2 of `Mesh`)
+/// so position-only passes like shadows do not fetch them
+#[derive(BufferContents, Vertex, Copy, Clone, Default)]
+#[repr(C)]
+pub struct NormalVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+}
+
+/// Up to 4 joints per vertex, in their own buffer (binding 3 of `SkinnedMesh`).
+/// Weights should add up to 1.
+#[derive(BufferContents, Vertex, Copy, Clone, Default)]
+#[repr(C)]
+pub struct SkinVertex {
+    #[format(R32G32B32A32_UINT)]
+    pub joints: [u32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub weights: [f32; 4],
 }
\ No newline at end of file
@@ -1,17 +1,20 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use vulkano::command_buffer::allocator::{StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
-use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags};
-use vulkano::image::ImageUsage;
+use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags};
+use vulkano::image::{view::ImageView, ImageUsage};
 use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano::swapchain::{Surface, Swapchain, SwapchainCreateInfo};
-use vulkano::VulkanLibrary;
+use vulkano::{Version, VulkanLibrary};
 use vulkano::instance::{Instance, InstanceCreateInfo};
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 
+use super::cache::{ImageViewCache, PipelineCacheFile, SamplerCache};
+use super::reflect::ReflectionCache;
+
 pub struct MemAllocators {
     pub memory: Arc<StandardMemoryAllocator>,
     pub command: Arc<StandardCommandBufferAllocator>,
@@ -52,6 +55,16 @@ pub struct Vk {
     pub instance: Arc<Instance>,
     pub surface: Arc<Surface>,
     //pub window: Arc<Window>,
+    pub samplers: SamplerCache,
+    pub image_views: ImageViewCache,
+    /// Descriptor names of the pipelines, see `Mesh::bind_model`
+    pub reflections: ReflectionCache,
+    /// Saved when `Vk` is dropped, call `pipeline_cache.save()` if the process may exit before that
+    pub pipeline_cache: PipelineCacheFile,
+    /// `descriptor_indexing` features are enabled, see `bindless::BindlessTextures`
+    pub bindless: bool,
+    /// Stand-in for missing material textures, see `mesh::material::white_texture`
+    pub white_texture: OnceLock<Arc<ImageView>>,
 }
 
 impl Vk {
@@ -107,6 +120,33 @@ impl Vk {
         //     println!("Found a queue family with {:?} queue(s)", family.queue_count);
         // }
             
+        let bindless_features = Features {
+            runtime_descriptor_array: true,
+            descriptor_binding_partially_bound: true,
+            descriptor_binding_variable_descriptor_count: true,
+            shader_sampled_image_array_non_uniform_indexing: true,
+            ..Features::empty()
+        };
+        /* core since 1.2, an extension before that. On 1.0 the extension also needs maintenance3 and
+        get_physical_device_properties2, which the instance does not enable: no bindless there */
+        let api_version = physical_device.api_version();
+        let bindless = physical_device.supported_features().contains(&bindless_features)
+            && (api_version >= Version::V1_2
+                || api_version >= Version::V1_1 && physical_device.supported_extensions().ext_descriptor_indexing);
+
+        let mut device_extensions = device_extensions;
+        if bindless && api_version < Version::V1_2 {
+            device_extensions.ext_descriptor_indexing = true;
+        }
+
+        let mut enabled_features = Features {
+            sampler_anisotropy: physical_device.supported_features().sampler_anisotropy,
+            ..Features::empty()
+        };
+        if bindless {
+            enabled_features = enabled_features.union(&bindless_features);
+        }
+
         let (device, mut queues) = Device::new(
                 physical_device.clone(),
                 DeviceCreateInfo {
@@ -115,6 +155,7 @@ impl Vk {
                         ..Default::default()
                     }],
                     enabled_extensions: device_extensions,
+                    enabled_features,
                     ..Default::default()
                 },
             )
@@ -124,6 +165,9 @@ impl Vk {
     
         let queue = queues.next().unwrap();
 
+        let samplers = SamplerCache::new(device.clone());
+        let pipeline_cache = PipelineCacheFile::load(device.clone(), PipelineCacheFile::default_dir());
+
         (Arc::new(Self {
             queue,
             physical_device,
@@ -132,6 +176,12 @@ impl Vk {
             allocators: Arc::new(allocators),
             instance: instance,
             surface,
+            samplers,
+            image_views: ImageViewCache::new(),
+            reflections: ReflectionCache::new(),
+            pipeline_cache,
+            bindless,
+            white_texture: OnceLock::new(),
         }), window)
     }
 }
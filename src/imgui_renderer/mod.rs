@@ -85,7 +85,7 @@ impl ImGui {
     /// Returns a sec renderpass given the target image to render to
     pub fn get_renderpasses(&mut self, target_images: Vec<Arc<Image>>, vk: Arc<Vk>) -> Vec<VkSecRenderpass> {
         let mut renderpasses = Vec::new();
-        let framebuffers = framebuffers(self.renderer.render_pass.clone(), &target_images);
+        let framebuffers = framebuffers(vk.clone(), self.renderer.render_pass.clone(), &target_images);
         let draw_data = self.ctx.render();
 
         for framebuffer in &framebuffers {
@@ -3,7 +3,7 @@
 
 use ahash::HashSetExt;
 use smallvec::smallvec;
-use vulkano::{buffer::BufferUsage, command_buffer::{allocator::StandardCommandBufferAllocator, CopyBufferToImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::{DescriptorImageViewInfo, WriteDescriptorSet, WriteDescriptorSetElements}, image::{sampler::{Filter, Sampler, SamplerCreateInfo, SamplerMipmapMode}, view::{ImageView, ImageViewCreateInfo, ImageViewType}, Image, ImageAspects, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter}, pipeline::{graphics::{color_blend::ColorBlendState, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition, VertexInputState}, viewport::{Scissor, Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, DynamicState, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::RenderPass, shader::ShaderStages};
+use vulkano::{buffer::BufferUsage, command_buffer::{allocator::StandardCommandBufferAllocator, CopyBufferToImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents}, descriptor_set::{DescriptorImageViewInfo, WriteDescriptorSet, WriteDescriptorSetElements}, image::{sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode}, view::{ImageView, ImageViewCreateInfo, ImageViewType}, Image, ImageAspects, ImageCreateInfo, ImageSubresourceRange, ImageType, ImageUsage}, memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryTypeFilter}, pipeline::{graphics::{color_blend::ColorBlendState, input_assembly::InputAssemblyState, multisample::MultisampleState, rasterization::RasterizationState, vertex_input::{Vertex, VertexDefinition, VertexInputState}, viewport::{Scissor, Viewport, ViewportState}, GraphicsPipelineCreateInfo}, layout::PipelineDescriptorSetLayoutCreateInfo, DynamicState, Pipeline, PipelineLayout, PipelineShaderStageCreateInfo}, render_pass::RenderPass, shader::ShaderStages};
 use vulkano::command_buffer::AutoCommandBufferBuilder;
 use vulkano::device::{Device, Queue};
 use vulkano::pipeline::GraphicsPipeline;
@@ -20,7 +20,7 @@ use std::fmt;
 use imgui::{DrawVert, Textures, DrawCmd, DrawCmdParams, internal::RawWrapper, TextureId, ImString};
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex as VulkanoVertex};
 
-use crate::graphics::{buffer::VkIterBuffer, command::{submit_cmd_buf, SecBuilderType, VkBuilder}, image::VkImage, utils::{descriptor_set, framebuffers}, vk::Vk};
+use crate::graphics::{buffer::VkIterBuffer, cache::SamplerDesc, command::{submit_cmd_buf, SecBuilderType, VkBuilder}, image::VkImage, pipeline::{BlendPreset, GraphicsPipelineBuilder}, utils::{descriptor_set, framebuffers}, vk::Vk};
 
 #[derive(Default, Debug, Clone, VulkanoVertex, BufferContents)]
 #[repr(C)]
@@ -56,26 +56,6 @@ impl ImRenderer {
         let vs = super::shaders::imvs::load(vk.device.clone()).unwrap();
         let fs = super::shaders::imfs::load(vk.device.clone()).unwrap();
         
-        let vs_entry = vs.entry_point("main").unwrap();
-        let fs_entry = fs.entry_point("main").unwrap();
-
-        let vertex_input_state = ImVertex::per_vertex()
-            .definition(&vs_entry.info().input_interface)
-            .unwrap();
-
-        let stages = [
-            PipelineShaderStageCreateInfo::new(vs_entry),
-            PipelineShaderStageCreateInfo::new(fs_entry),
-        ];
-        
-        let layout = PipelineLayout::new(
-            vk.device.clone(), 
-            PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
-                .into_pipeline_layout_create_info(vk.device.clone())
-                .unwrap(),
-        )
-        .unwrap();
-        
         let render_pass = vulkano::single_pass_renderpass!(vk.device.clone(),
                 attachments: {
                     color_attachment: {
@@ -94,36 +74,12 @@ impl ImRenderer {
     
         let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
 
-        let mut dyn_state = ahash::HashSet::new();
-        dyn_state.insert(DynamicState::ViewportWithCount);
-        dyn_state.insert(DynamicState::ScissorWithCount);
-
-        let pipeline = GraphicsPipeline::new(
-            vk.device.clone(),
-            None,
-            GraphicsPipelineCreateInfo {
-                stages: stages.into_iter().collect(),
-
-                vertex_input_state: Some(vertex_input_state),
-                
-                input_assembly_state: Some(InputAssemblyState::new().topology(vulkano::pipeline::graphics::input_assembly::PrimitiveTopology::TriangleList)),
-        
-                viewport_state: Some(ViewportState::new()),
-
-                rasterization_state: Some(RasterizationState::default()),
-
-                multisample_state: Some(MultisampleState::default()),
-                
-                color_blend_state: Some(ColorBlendState::new(1).blend_alpha()),
-
-                subpass: Some(vulkano::pipeline::graphics::subpass::PipelineSubpassType::BeginRenderPass(subpass.clone())),
-
-                dynamic_state: dyn_state,
-
-                ..GraphicsPipelineCreateInfo::layout(layout)
-            }
-        )
-        .unwrap();
+        let pipeline = GraphicsPipelineBuilder::new(vs, fs)
+            .vertex::<ImVertex>()
+            .blend(BlendPreset::Alpha)
+            .dynamic_state(DynamicState::ViewportWithCount)
+            .dynamic_state(DynamicState::ScissorWithCount)
+            .build(vk.clone(), render_pass.clone());
 
         let textures = Textures::new();
 
@@ -224,7 +180,7 @@ impl ImRenderer {
                             (clip_rect[3] - clip_rect[1]) as u32,
                         ];
 
-                        let tex = self.lookup_texture();
+                        let tex = self.lookup_texture(vk.clone());
                         let sampler = self.get_sampler(vk.clone());
 
                         let set = descriptor_set(
@@ -320,9 +276,9 @@ impl ImRenderer {
         image.content
     }
 
-    fn lookup_texture(&self) -> Option<Arc<ImageView>> {
-        return Some(ImageView::new(
-            self.font_texture.clone(), 
+    fn lookup_texture(&self, vk: Arc<Vk>) -> Option<Arc<ImageView>> {
+        return Some(vk.image_views.get(
+            &self.font_texture, 
             ImageViewCreateInfo { 
                 format: self.format, 
                 usage: ImageUsage::SAMPLED,
@@ -333,19 +289,11 @@ impl ImRenderer {
                 },
                 ..Default::default()
             }
-        ).unwrap());
+        ));
     }
 
     fn get_sampler(&self, vk: Arc<Vk>) -> Arc<vulkano::image::sampler::Sampler> {
-        Sampler::new(
-            vk.device.clone(),
-            SamplerCreateInfo {
-                mag_filter: Filter::Linear,
-                min_filter: Filter::Linear,
-                mipmap_mode: SamplerMipmapMode::Linear,
-                ..Default::default()
-            },
-        ).unwrap()
+        vk.samplers.get(SamplerDesc::linear().address_mode(SamplerAddressMode::ClampToEdge))
     }
 }
 